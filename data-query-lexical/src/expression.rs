@@ -0,0 +1,1025 @@
+use crate::lexer::{
+    generic_object_index, optional, recursive_descent, LexOperator, LexicalOperations,
+};
+use crate::lexer_constants::*;
+use crate::{LexResult, LexerError};
+use serde_json::Value;
+use std::collections::LinkedList;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Comparator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    /// `=~`: the left hand side is a string matched by the regular expression on the right.
+    RegexMatch,
+}
+
+impl Comparator {
+    fn token(&self) -> &'static str {
+        match self {
+            Comparator::Equal => LEX_EQUAL,
+            Comparator::NotEqual => LEX_NOT_EQUAL,
+            Comparator::Less => LEX_LESS,
+            Comparator::LessOrEqual => LEX_LESS_EQUAL,
+            Comparator::Greater => LEX_GREATER,
+            Comparator::GreaterOrEqual => LEX_GREATER_EQUAL,
+            Comparator::RegexMatch => LEX_REGEX_MATCH,
+        }
+    }
+}
+
+/// Expression evaluated against a single value, such as the predicate of a `[?(...)]` filter.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Expression {
+    /// Path relative to the value under test, `.` being the value itself.
+    Path(LexicalOperations),
+    Literal(Value),
+    Compare(Box<Expression>, Comparator, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// `name` or `name(arg; ...)`: a builtin function applied to the value under test. The
+    /// arguments are expressions evaluated against that same value.
+    Function(String, Vec<Expression>),
+    /// `lhs | rhs`: every value produced by the left hand side is fed to the right hand side.
+    Pipe(Box<Expression>, Box<Expression>),
+    /// `{name: .metadata.name, (.key): .value}`: an object built from key and value
+    /// expressions. A key or value producing several results builds an object for each
+    /// combination of them.
+    Object(Vec<(Expression, Expression)>),
+    /// `lhs, rhs`: every value produced by the left hand side, followed by every value produced
+    /// by the right hand side.
+    Comma(Box<Expression>, Box<Expression>),
+    /// `[expression]`: a single array holding every value the expression produces.
+    Collect(Box<Expression>),
+}
+
+impl Display for Comparator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.token())
+    }
+}
+
+impl Expression {
+    /// Binding strength of the expression, used to decide where parentheses are required.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::Pipe(_, _) => 0,
+            Expression::Comma(_, _) => 1,
+            Expression::Or(_, _) => 2,
+            Expression::And(_, _) => 3,
+            Expression::Not(_) => 4,
+            Expression::Compare(_, _, _) => 5,
+            Expression::Path(_)
+            | Expression::Literal(_)
+            | Expression::Function(_, _)
+            | Expression::Object(_)
+            | Expression::Collect(_) => 6,
+        }
+    }
+
+    /// Turns the expression into lexical operations, keeping plain paths as path operators so
+    /// that `.a | .b` compiles to the same operations whether or not it is part of a pipeline.
+    pub(crate) fn into_operations(self) -> LinkedList<LexOperator> {
+        match self {
+            Expression::Path(path) => path.0,
+            Expression::Pipe(lhs, rhs) => {
+                let mut operators = lhs.into_operations();
+                push_pipe(&mut operators, rhs.into_operations());
+                operators
+            }
+            e => LinkedList::from([LexOperator::Expression(e)]),
+        }
+    }
+}
+
+/// Appends a pipe to `operators`. A pipe takes the rest of the query, so when `operators` already
+/// ends with one, as the left hand side of `a | b | c` does, the new pipe goes inside it.
+fn push_pipe(operators: &mut LinkedList<LexOperator>, rhs: LinkedList<LexOperator>) {
+    match operators.back_mut() {
+        Some(LexOperator::Pipe(inner)) => push_pipe(inner, rhs),
+        _ => operators.push_back(LexOperator::Pipe(rhs)),
+    }
+}
+
+/// Whether `key` can be written as a bare object key, as in `{name: .name}`.
+fn is_bare_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && ![LEX_TRUE, LEX_FALSE, LEX_NULL, LEX_AND, LEX_OR, LEX_NOT].contains(&key)
+}
+
+/// Writes `e`, in parentheses if it binds looser than `precedence`.
+fn write_grouped(f: &mut Formatter<'_>, e: &Expression, precedence: u8) -> std::fmt::Result {
+    if e.precedence() < precedence {
+        write!(f, "{}{}{}", LEX_CAPSULE_START, e, LEX_CAPSULE_END)
+    } else {
+        write!(f, "{}", e)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let precedence = self.precedence();
+        match self {
+            Expression::Path(p) => write!(f, "{}", p),
+            Expression::Literal(l) => write!(f, "{}", l),
+            Expression::Compare(l, c, r) => {
+                write_grouped(f, l, precedence + 1)?;
+                write!(f, " {} ", c)?;
+                write_grouped(f, r, precedence + 1)
+            }
+            Expression::And(l, r) | Expression::Or(l, r) => {
+                let keyword = match self {
+                    Expression::And(_, _) => LEX_AND,
+                    _ => LEX_OR,
+                };
+                // Both operators are left associative, so only the right hand side of a chain
+                // of the same operator needs parentheses.
+                write_grouped(f, l, precedence)?;
+                write!(f, " {} ", keyword)?;
+                write_grouped(f, r, precedence + 1)
+            }
+            Expression::Not(e) => {
+                write!(f, "{} ", LEX_NOT)?;
+                write_grouped(f, e, precedence)
+            }
+            Expression::Function(name, arguments) => {
+                write!(f, "{}", name)?;
+                if !arguments.is_empty() {
+                    write!(f, "{}", LEX_CAPSULE_START)?;
+                    for (i, argument) in arguments.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, "{} ", LEX_ARGUMENT_SEPARATOR)?;
+                        }
+                        write!(f, "{}", argument)?;
+                    }
+                    write!(f, "{}", LEX_CAPSULE_END)?;
+                }
+                Ok(())
+            }
+            Expression::Pipe(l, r) => {
+                write_grouped(f, l, precedence)?;
+                write!(f, " {} ", LEX_PIPE)?;
+                write_grouped(f, r, precedence + 1)
+            }
+            Expression::Comma(l, r) => {
+                write_grouped(f, l, precedence)?;
+                write!(f, "{} ", LEX_GENERIC_SEPARATOR)?;
+                write_grouped(f, r, precedence + 1)
+            }
+            Expression::Collect(e) => write!(f, "{}{}{}", LEX_GENERIC_START, e, LEX_GENERIC_END),
+            Expression::Object(entries) => {
+                write!(f, "{}", LEX_OBJECT_START)?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{} ", LEX_OBJECT_SEPARATOR)?;
+                    }
+                    match key {
+                        Expression::Literal(Value::String(k)) if is_bare_key(k) => {
+                            write!(f, "{}", k)?
+                        }
+                        Expression::Literal(k @ Value::String(_)) => write!(f, "{}", k)?,
+                        k => write!(f, "{}{}{}", LEX_CAPSULE_START, k, LEX_CAPSULE_END)?,
+                    }
+                    // A comma ends the entry, so pipes and commas in the value are written in
+                    // parentheses, that is anything looser than `or`.
+                    write!(f, "{} ", LEX_OBJECT_KEY_END)?;
+                    write_grouped(f, value, 2)?;
+                }
+                write!(f, "{}", LEX_OBJECT_END)
+            }
+        }
+    }
+}
+
+pub(crate) fn unexpected(expected: &str, lexer_vec: &[char], char_pointer: usize) -> LexerError {
+    match lexer_vec.last() {
+        Some(c) => LexerError::UnexpectedCharacter {
+            expected: expected.to_string(),
+            found: c.to_string(),
+            char_pointer,
+            lex: format!("{:?}", lexer_vec),
+        },
+        None => LexerError::EndOfQuery {
+            expected: expected.to_string(),
+            char_pointer,
+            lex: format!("{:?}", lexer_vec),
+        },
+    }
+}
+
+pub(crate) fn skip_whitespace(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) {
+    while lexer_vec.last().is_some_and(|c| c.is_whitespace()) {
+        lexer_vec.pop();
+        *char_pointer += 1;
+    }
+}
+
+/// Consumes `expected` after any whitespace, or fails without consuming anything.
+pub(crate) fn expect(
+    lexer_vec: &mut Vec<char>,
+    char_pointer: &mut usize,
+    expected: char,
+) -> LexResult<()> {
+    skip_whitespace(lexer_vec, char_pointer);
+    if lexer_vec.last() == Some(&expected) {
+        lexer_vec.pop();
+        *char_pointer += 1;
+        Ok(())
+    } else {
+        Err(unexpected(&expected.to_string(), lexer_vec, *char_pointer))
+    }
+}
+
+/// Consumes `token` if the remaining query starts with it.
+fn consume(lexer_vec: &mut Vec<char>, char_pointer: &mut usize, token: &str) -> bool {
+    let len = token.chars().count();
+    if lexer_vec.len() >= len && lexer_vec.iter().rev().take(len).copied().eq(token.chars()) {
+        lexer_vec.truncate(lexer_vec.len() - len);
+        *char_pointer += len;
+        true
+    } else {
+        false
+    }
+}
+
+/// Reads an identifier up to the next expression delimiter, honouring escapes.
+pub(crate) fn identifier(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> String {
+    let mut collect = String::new();
+    let mut escape = false;
+    while let Some(c) = lexer_vec.last().copied() {
+        if escape {
+            collect.push(c);
+            escape = false;
+        } else if c == LEX_ESCAPE {
+            escape = true;
+        } else if LEX_EXPRESSION_DELIMITERS.contains(&c) || c.is_whitespace() {
+            break;
+        } else {
+            collect.push(c);
+        }
+        lexer_vec.pop();
+        *char_pointer += 1;
+    }
+    collect
+}
+
+/// Consumes the keyword `word` if the remaining query starts with it as a whole word.
+fn keyword(lexer_vec: &mut Vec<char>, char_pointer: &mut usize, word: &str) -> bool {
+    let len = word.chars().count();
+    let boundary = lexer_vec.len() == len
+        || lexer_vec.len() > len && {
+            let next = lexer_vec[lexer_vec.len() - len - 1];
+            LEX_EXPRESSION_DELIMITERS.contains(&next) || next.is_whitespace()
+        };
+    boundary && consume(lexer_vec, char_pointer, word)
+}
+
+/// Parses a full expression, stopping at the first character that cannot continue it.
+///
+/// From loosest to tightest binding the grammar is `|`, `,`, `or`, `and`, `not`, the
+/// comparisons and finally the operands, where parentheses group a nested expression.
+pub(crate) fn expression(
+    lexer_vec: &mut Vec<char>,
+    char_pointer: &mut usize,
+) -> LexResult<Expression> {
+    let first = disjunction(lexer_vec, char_pointer)?;
+    continue_expression(first, lexer_vec, char_pointer)
+}
+
+/// Parses the rest of an expression whose first operand has already been read, such as the
+/// path `.name` of `.name, .id`.
+pub(crate) fn continue_expression(
+    first: Expression,
+    lexer_vec: &mut Vec<char>,
+    char_pointer: &mut usize,
+) -> LexResult<Expression> {
+    let mut lhs = comma(first, lexer_vec, char_pointer)?;
+    loop {
+        skip_whitespace(lexer_vec, char_pointer);
+        if lexer_vec.last() != Some(&LEX_PIPE) {
+            return Ok(lhs);
+        }
+        lexer_vec.pop();
+        *char_pointer += 1;
+        let rhs = disjunction(lexer_vec, char_pointer)?;
+        let rhs = comma(rhs, lexer_vec, char_pointer)?;
+        lhs = Expression::Pipe(Box::new(lhs), Box::new(rhs));
+    }
+}
+
+fn comma(
+    mut lhs: Expression,
+    lexer_vec: &mut Vec<char>,
+    char_pointer: &mut usize,
+) -> LexResult<Expression> {
+    loop {
+        skip_whitespace(lexer_vec, char_pointer);
+        if lexer_vec.last() != Some(&LEX_GENERIC_SEPARATOR) {
+            return Ok(lhs);
+        }
+        lexer_vec.pop();
+        *char_pointer += 1;
+        let rhs = disjunction(lexer_vec, char_pointer)?;
+        lhs = Expression::Comma(Box::new(lhs), Box::new(rhs));
+    }
+}
+
+/// Parses the value of an object entry, which may be a pipeline but ends at the `,` that
+/// separates it from the next entry.
+fn object_value(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    let mut lhs = disjunction(lexer_vec, char_pointer)?;
+    loop {
+        skip_whitespace(lexer_vec, char_pointer);
+        if lexer_vec.last() != Some(&LEX_PIPE) {
+            return Ok(lhs);
+        }
+        lexer_vec.pop();
+        *char_pointer += 1;
+        let rhs = disjunction(lexer_vec, char_pointer)?;
+        lhs = Expression::Pipe(Box::new(lhs), Box::new(rhs));
+    }
+}
+
+fn disjunction(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    let mut lhs = conjunction(lexer_vec, char_pointer)?;
+    loop {
+        skip_whitespace(lexer_vec, char_pointer);
+        if !keyword(lexer_vec, char_pointer, LEX_OR) {
+            return Ok(lhs);
+        }
+        let rhs = conjunction(lexer_vec, char_pointer)?;
+        lhs = Expression::Or(Box::new(lhs), Box::new(rhs));
+    }
+}
+
+fn conjunction(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    let mut lhs = negation(lexer_vec, char_pointer)?;
+    loop {
+        skip_whitespace(lexer_vec, char_pointer);
+        if !keyword(lexer_vec, char_pointer, LEX_AND) {
+            return Ok(lhs);
+        }
+        let rhs = negation(lexer_vec, char_pointer)?;
+        lhs = Expression::And(Box::new(lhs), Box::new(rhs));
+    }
+}
+
+fn negation(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    skip_whitespace(lexer_vec, char_pointer);
+    if keyword(lexer_vec, char_pointer, LEX_NOT) {
+        skip_whitespace(lexer_vec, char_pointer);
+        // Without an operand, as in `.enabled | not`, `not` negates the value itself.
+        let operand = match lexer_vec.last() {
+            None
+            | Some(&LEX_PIPE)
+            | Some(&LEX_CAPSULE_END)
+            | Some(&LEX_ARGUMENT_SEPARATOR)
+            | Some(&LEX_OBJECT_SEPARATOR)
+            | Some(&LEX_OBJECT_END)
+            | Some(&LEX_GENERIC_END) => Expression::Path(LinkedList::new().into()),
+            _ => negation(lexer_vec, char_pointer)?,
+        };
+        Ok(Expression::Not(Box::new(operand)))
+    } else {
+        comparison(lexer_vec, char_pointer)
+    }
+}
+
+fn comparison(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    let lhs = operand(lexer_vec, char_pointer)?;
+    skip_whitespace(lexer_vec, char_pointer);
+    let comparator = [
+        (LEX_EQUAL, Comparator::Equal),
+        (LEX_REGEX_MATCH, Comparator::RegexMatch),
+        (LEX_NOT_EQUAL, Comparator::NotEqual),
+        (LEX_LESS_EQUAL, Comparator::LessOrEqual),
+        (LEX_LESS, Comparator::Less),
+        (LEX_GREATER_EQUAL, Comparator::GreaterOrEqual),
+        (LEX_GREATER, Comparator::Greater),
+    ]
+    .into_iter()
+    .find(|(token, _)| consume(lexer_vec, char_pointer, token));
+    match comparator {
+        Some((_, comparator)) => {
+            let rhs = operand(lexer_vec, char_pointer)?;
+            Ok(Expression::Compare(
+                Box::new(lhs),
+                comparator,
+                Box::new(rhs),
+            ))
+        }
+        None => Ok(lhs),
+    }
+}
+
+fn operand(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    skip_whitespace(lexer_vec, char_pointer);
+    match lexer_vec.last().copied() {
+        Some(LEX_CAPSULE_START) => {
+            lexer_vec.pop();
+            *char_pointer += 1;
+            let group = expression(lexer_vec, char_pointer)?;
+            expect(lexer_vec, char_pointer, LEX_CAPSULE_END)?;
+            Ok(group)
+        }
+        Some(LEX_IDENTIFIER) => path(lexer_vec, char_pointer).map(Expression::Path),
+        Some(LEX_OBJECT_START) => object(lexer_vec, char_pointer),
+        Some(LEX_GENERIC_START) => {
+            lexer_vec.pop();
+            *char_pointer += 1;
+            skip_whitespace(lexer_vec, char_pointer);
+            if lexer_vec.last() == Some(&LEX_GENERIC_END) {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                return Ok(Expression::Literal(Value::Array(Vec::new())));
+            }
+            let collect = expression(lexer_vec, char_pointer)?;
+            expect(lexer_vec, char_pointer, LEX_GENERIC_END)?;
+            Ok(Expression::Collect(Box::new(collect)))
+        }
+        Some(LEX_STRING_QUOTE) => string_literal(lexer_vec, char_pointer).map(Expression::Literal),
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            number_literal(lexer_vec, char_pointer).map(Expression::Literal)
+        }
+        Some(c) if c.is_alphabetic() => {
+            let start = *char_pointer;
+            match identifier(lexer_vec, char_pointer).as_str() {
+                LEX_TRUE => Ok(Expression::Literal(Value::Bool(true))),
+                LEX_FALSE => Ok(Expression::Literal(Value::Bool(false))),
+                LEX_NULL => Ok(Expression::Literal(Value::Null)),
+                name if name.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                    let name = name.to_string();
+                    function_arguments(lexer_vec, char_pointer)
+                        .map(|arguments| Expression::Function(name, arguments))
+                }
+                word => Err(LexerError::UnexpectedCharacter {
+                    expected: "Path/Literal/Function".to_string(),
+                    found: word.to_string(),
+                    char_pointer: start,
+                    lex: format!("{:?}", lexer_vec),
+                }),
+            }
+        }
+        _ => Err(unexpected("Path/Literal", lexer_vec, *char_pointer)),
+    }
+}
+
+/// Parses an object construction such as `{name, ns: .metadata.namespace, (.key): .value}`.
+///
+/// A key is a bare word, a quoted string or a parenthesised expression. A bare or quoted key
+/// without a value, as in `{name}`, takes the value of that key on the input.
+fn object(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Expression> {
+    expect(lexer_vec, char_pointer, LEX_OBJECT_START)?;
+    let mut entries = Vec::new();
+    skip_whitespace(lexer_vec, char_pointer);
+    if lexer_vec.last() == Some(&LEX_OBJECT_END) {
+        lexer_vec.pop();
+        *char_pointer += 1;
+        return Ok(Expression::Object(entries));
+    }
+    loop {
+        skip_whitespace(lexer_vec, char_pointer);
+        let key = match lexer_vec.last().copied() {
+            Some(LEX_CAPSULE_START) => {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                let key = expression(lexer_vec, char_pointer)?;
+                expect(lexer_vec, char_pointer, LEX_CAPSULE_END)?;
+                key
+            }
+            Some(LEX_STRING_QUOTE) => {
+                Expression::Literal(Value::String(quoted_key(lexer_vec, char_pointer)?))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let mut key = String::new();
+                while let Some(c) = lexer_vec
+                    .last()
+                    .copied()
+                    .filter(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    key.push(c);
+                    lexer_vec.pop();
+                    *char_pointer += 1;
+                }
+                Expression::Literal(Value::String(key))
+            }
+            _ => return Err(unexpected("Object key", lexer_vec, *char_pointer)),
+        };
+        skip_whitespace(lexer_vec, char_pointer);
+        let value = match (lexer_vec.last().copied(), &key) {
+            (Some(LEX_OBJECT_KEY_END), _) => {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                object_value(lexer_vec, char_pointer)?
+            }
+            (
+                Some(LEX_OBJECT_SEPARATOR | LEX_OBJECT_END),
+                Expression::Literal(Value::String(k)),
+            ) => Expression::Path(LinkedList::from([LexOperator::Identifier(k.clone())]).into()),
+            _ => {
+                return Err(unexpected(
+                    &LEX_OBJECT_KEY_END.to_string(),
+                    lexer_vec,
+                    *char_pointer,
+                ))
+            }
+        };
+        entries.push((key, value));
+        skip_whitespace(lexer_vec, char_pointer);
+        let end = match lexer_vec.last().copied() {
+            Some(LEX_OBJECT_SEPARATOR) => false,
+            Some(LEX_OBJECT_END) => true,
+            _ => {
+                return Err(unexpected(
+                    &format!("{} or {}", LEX_OBJECT_SEPARATOR, LEX_OBJECT_END),
+                    lexer_vec,
+                    *char_pointer,
+                ))
+            }
+        };
+        lexer_vec.pop();
+        *char_pointer += 1;
+        if end {
+            return Ok(Expression::Object(entries));
+        }
+    }
+}
+
+/// Parses the `(arg; ...)` following a function name, if there is one.
+fn function_arguments(
+    lexer_vec: &mut Vec<char>,
+    char_pointer: &mut usize,
+) -> LexResult<Vec<Expression>> {
+    let mut arguments = Vec::new();
+    if lexer_vec.last() != Some(&LEX_CAPSULE_START) {
+        return Ok(arguments);
+    }
+    lexer_vec.pop();
+    *char_pointer += 1;
+    loop {
+        arguments.push(expression(lexer_vec, char_pointer)?);
+        skip_whitespace(lexer_vec, char_pointer);
+        if lexer_vec.last() == Some(&LEX_ARGUMENT_SEPARATOR) {
+            lexer_vec.pop();
+            *char_pointer += 1;
+        } else {
+            expect(lexer_vec, char_pointer, LEX_CAPSULE_END)?;
+            return Ok(arguments);
+        }
+    }
+}
+
+/// Parses a relative path made of `.key`, `..key` and `[...]` segments.
+fn path(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<LexicalOperations> {
+    let mut operators = LinkedList::new();
+    loop {
+        match lexer_vec.last().copied() {
+            Some(LEX_IDENTIFIER) => {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                if lexer_vec.last() == Some(&LEX_IDENTIFIER) {
+                    lexer_vec.pop();
+                    *char_pointer += 1;
+                    let (segment, pointer) = recursive_descent(lexer_vec, *char_pointer)?;
+                    operators.push_back(LexOperator::RecursiveDescent(Box::new(segment)));
+                    *char_pointer = pointer;
+                } else if lexer_vec.last() == Some(&LEX_STRING_QUOTE) {
+                    let key = quoted_key(lexer_vec, char_pointer)?;
+                    operators.push_back(LexOperator::Identifier(key));
+                } else {
+                    let ident = identifier(lexer_vec, char_pointer);
+                    if !ident.is_empty() {
+                        operators.push_back(LexOperator::Identifier(ident));
+                    }
+                }
+            }
+            Some(LEX_GENERIC_START) => {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                let generic = generic_object_index(
+                    lexer_vec,
+                    Default::default(),
+                    LinkedList::new(),
+                    None,
+                    false,
+                    *char_pointer,
+                )?;
+                operators.push_back(LexOperator::Generic(generic));
+            }
+            Some(LEX_OPTIONAL) => {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                optional(&mut operators, *char_pointer)?;
+            }
+            _ => return Ok(operators.into()),
+        }
+    }
+}
+
+/// Reads a quoted key such as `"prometheus.io/port"`, which takes the escapes of a JSON string.
+pub(crate) fn quoted_key(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<String> {
+    match string_literal(lexer_vec, char_pointer)? {
+        Value::String(key) => Ok(key),
+        other => Ok(other.to_string()),
+    }
+}
+
+fn string_literal(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Value> {
+    let start = *char_pointer;
+    let mut collect = String::new();
+    let mut escape = false;
+    while let Some(c) = lexer_vec.pop() {
+        *char_pointer += 1;
+        collect.push(c);
+        if escape {
+            escape = false;
+        } else if c == LEX_ESCAPE {
+            escape = true;
+        } else if c == LEX_STRING_QUOTE && collect.len() > 1 {
+            return serde_json::from_str(&collect).map_err(|e| LexerError::UnexpectedCharacter {
+                expected: "String".to_string(),
+                found: e.to_string(),
+                char_pointer: start,
+                lex: collect,
+            });
+        }
+    }
+    Err(unexpected(
+        &LEX_STRING_QUOTE.to_string(),
+        lexer_vec,
+        *char_pointer,
+    ))
+}
+
+fn number_literal(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> LexResult<Value> {
+    let start = *char_pointer;
+    let mut collect = String::new();
+    while let Some(c) = lexer_vec.last().copied() {
+        let sign = (c == '-' || c == '+') && (collect.is_empty() || collect.ends_with(['e', 'E']));
+        if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || sign {
+            collect.push(c);
+            lexer_vec.pop();
+            *char_pointer += 1;
+        } else {
+            break;
+        }
+    }
+    match serde_json::from_str::<Value>(&collect) {
+        Ok(number @ Value::Number(_)) => Ok(number),
+        _ => Err(LexerError::UnexpectedCharacter {
+            expected: "Number".to_string(),
+            found: collect,
+            char_pointer: start,
+            lex: format!("{:?}", lexer_vec),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::expression::{Comparator, Expression};
+    use crate::lexer::LexOperator::*;
+    use crate::{compile, GenericObjectIndex, LexOperator, LexResult, LexicalOperations};
+    use serde_json::json;
+    use std::collections::LinkedList;
+
+    fn path(operators: &[&str]) -> Box<Expression> {
+        Box::new(Expression::Path(
+            operators
+                .iter()
+                .map(|o| Identifier(o.to_string()))
+                .collect::<LinkedList<_>>()
+                .into(),
+        ))
+    }
+
+    fn literal(value: serde_json::Value) -> Box<Expression> {
+        Box::new(Expression::Literal(value))
+    }
+
+    #[test]
+    pub fn test_filter() {
+        let compiled_lex = compile(r#".spec.containers[?(.name == "wordpress")].image"#);
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("spec".to_string()),
+            Identifier("containers".to_string()),
+            Generic(GenericObjectIndex::Filter(Expression::Compare(
+                path(&["name"]),
+                Comparator::Equal,
+                literal(json!("wordpress")),
+            ))),
+            Identifier("image".to_string()),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+    }
+
+    #[test]
+    pub fn test_filter_operands() {
+        let cases = [
+            (
+                "[?(.age>=30)]",
+                Expression::Compare(
+                    path(&["age"]),
+                    Comparator::GreaterOrEqual,
+                    literal(json!(30)),
+                ),
+            ),
+            (
+                "[?(. != -1.5e3)]",
+                Expression::Compare(path(&[]), Comparator::NotEqual, literal(json!(-1500.0))),
+            ),
+            (
+                "[?(.a.b < .c)]",
+                Expression::Compare(path(&["a", "b"]), Comparator::Less, path(&["c"])),
+            ),
+            (
+                "[?(null == .a)]",
+                Expression::Compare(literal(json!(null)), Comparator::Equal, path(&["a"])),
+            ),
+            (
+                r#"[?(.name > "a\"b")]"#,
+                Expression::Compare(path(&["name"]), Comparator::Greater, literal(json!("a\"b"))),
+            ),
+        ];
+        for (lex, expression) in cases {
+            let compiled_lex = compile(lex).unwrap();
+            assert_eq!(
+                compiled_lex.front(),
+                Some(&Generic(GenericObjectIndex::Filter(expression))),
+                "{}",
+                lex
+            );
+            assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        }
+    }
+
+    #[test]
+    pub fn test_filter_boolean_logic() {
+        let compiled_lex =
+            compile("[?(.runAsNonRoot == true and (.runAsUser > 1000 or .runAsUser == null))]");
+        let expression = Expression::And(
+            Box::new(Expression::Compare(
+                path(&["runAsNonRoot"]),
+                Comparator::Equal,
+                literal(json!(true)),
+            )),
+            Box::new(Expression::Or(
+                Box::new(Expression::Compare(
+                    path(&["runAsUser"]),
+                    Comparator::Greater,
+                    literal(json!(1000)),
+                )),
+                Box::new(Expression::Compare(
+                    path(&["runAsUser"]),
+                    Comparator::Equal,
+                    literal(json!(null)),
+                )),
+            )),
+        );
+        assert_eq!(
+            compiled_lex.unwrap().front(),
+            Some(&Generic(GenericObjectIndex::Filter(expression)))
+        );
+    }
+
+    #[test]
+    pub fn test_filter_precedence() {
+        let cases = [
+            ("[?(.a or .b and .c)]", "[?(.a or .b and .c)]"),
+            ("[?((.a or .b) and .c)]", "[?((.a or .b) and .c)]"),
+            ("[?(.a or (.b or .c))]", "[?(.a or (.b or .c))]"),
+            ("[?((.a or .b) or .c)]", "[?(.a or .b or .c)]"),
+            (
+                "[?(not .a == 1 and not (.b or .c))]",
+                "[?(not .a == 1 and not (.b or .c))]",
+            ),
+            ("[?(not not .order)]", "[?(not not .order)]"),
+            ("[?(.android and.x)]", "[?(.android and .x)]"),
+        ];
+        for (lex, display) in cases {
+            let compiled_lex = compile(lex).unwrap();
+            assert_eq!(compiled_lex.to_string(), format!(".{}", display), "{}", lex);
+            assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        }
+        assert_eq!(
+            compile("[?(.a or .b and .c)]"),
+            compile("[?(.a or (.b and .c))]")
+        );
+    }
+
+    #[test]
+    pub fn test_filter_functions() {
+        let regex = || literal(json!("^WORDPRESS_"));
+        let cases = [
+            (
+                r#"[?(.name =~ "^WORDPRESS_")]"#,
+                Expression::Compare(path(&["name"]), Comparator::RegexMatch, regex()),
+            ),
+            (
+                r#"[?(.name | test("^WORDPRESS_"; "i"))]"#,
+                Expression::Pipe(
+                    path(&["name"]),
+                    Box::new(Expression::Function(
+                        "test".to_string(),
+                        vec![*regex(), *literal(json!("i"))],
+                    )),
+                ),
+            ),
+            (
+                r#"[?(.a | not)]"#,
+                Expression::Pipe(path(&["a"]), Box::new(Expression::Not(path(&[])))),
+            ),
+            (
+                r#"[?(test(.pattern) and .b)]"#,
+                Expression::And(
+                    Box::new(Expression::Function(
+                        "test".to_string(),
+                        vec![*path(&["pattern"])],
+                    )),
+                    path(&["b"]),
+                ),
+            ),
+        ];
+        for (lex, expression) in cases {
+            let compiled_lex = compile(lex).unwrap();
+            assert_eq!(
+                compiled_lex.front(),
+                Some(&Generic(GenericObjectIndex::Filter(expression))),
+                "{}",
+                lex
+            );
+            assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        }
+    }
+
+    #[test]
+    pub fn test_pipe_into_function() {
+        let compiled_lex = compile(r#".env[*].name | sub("_"; "-"; "g") | .x"#).unwrap();
+        let true_result: LexicalOperations = LinkedList::from([
+            Identifier("env".to_string()),
+            Generic(GenericObjectIndex::Wildcard),
+            Identifier("name".to_string()),
+            Pipe(LinkedList::from([
+                LexOperator::Expression(Expression::Function(
+                    "sub".to_string(),
+                    vec![
+                        *literal(json!("_")),
+                        *literal(json!("-")),
+                        *literal(json!("g")),
+                    ],
+                )),
+                Pipe(LinkedList::from([Identifier("x".to_string())])),
+            ])),
+        ])
+        .into();
+        assert_eq!(compiled_lex, true_result);
+        assert_eq!(
+            compiled_lex.to_string(),
+            r#".env[*].name | sub("_"; "-"; "g") | .x"#
+        );
+
+        // Every later pipe nests inside the previous one rather than following it.
+        let compiled_lex = compile(".a | sort | .[*] | length").unwrap();
+        let true_result: LexicalOperations = LinkedList::from([
+            Identifier("a".to_string()),
+            Pipe(LinkedList::from([
+                LexOperator::Expression(Expression::Function("sort".to_string(), vec![])),
+                Pipe(LinkedList::from([
+                    Generic(GenericObjectIndex::Wildcard),
+                    Pipe(LinkedList::from([LexOperator::Expression(
+                        Expression::Function("length".to_string(), vec![]),
+                    )])),
+                ])),
+            ])),
+        ])
+        .into();
+        assert_eq!(compiled_lex, true_result);
+        assert_eq!(compiled_lex.to_string(), ".a | sort | .[*] | length");
+    }
+
+    #[test]
+    pub fn test_object_construction() {
+        let compiled_lex =
+            compile(r#".items[*] | {name, "ns": .metadata.namespace, (.kind): .spec | not}"#)
+                .unwrap();
+        let object = Expression::Object(vec![
+            (*literal(json!("name")), *path(&["name"])),
+            (*literal(json!("ns")), *path(&["metadata", "namespace"])),
+            (
+                *path(&["kind"]),
+                Expression::Pipe(path(&["spec"]), Box::new(Expression::Not(path(&[])))),
+            ),
+        ]);
+        let true_result: LexicalOperations = LinkedList::from([
+            Identifier("items".to_string()),
+            Generic(GenericObjectIndex::Wildcard),
+            Pipe(LinkedList::from([LexOperator::Expression(object)])),
+        ])
+        .into();
+        assert_eq!(compiled_lex, true_result);
+        assert_eq!(
+            compiled_lex.to_string(),
+            ".items[*] | {name: .name, ns: .metadata.namespace, (.kind): (.spec | not .)}"
+        );
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+
+        let cases = [
+            ("{}", "{}"),
+            (r#"{"a b": 1, "true"}"#, r#"{"a b": 1, "true": .true}"#),
+            ("{a: {b: .c}} | .a", "{a: {b: .c}} | .a"),
+            (
+                r#"{(.a | sub("x"; "y")): .b}"#,
+                r#"{(.a | sub("x"; "y")): .b}"#,
+            ),
+            ("[?({a: .b} == .c)]", ".[?({a: .b} == .c)]"),
+        ];
+        for (lex, display) in cases {
+            let compiled_lex = compile(lex).unwrap();
+            assert_eq!(compiled_lex.to_string(), display, "{}", lex);
+            assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        }
+
+        assert!(compile("{a: .b").is_err());
+        assert!(compile("{a .b}").is_err());
+        assert!(compile("{(.a)}").is_err());
+        assert!(compile("{1: .a}").is_err());
+        assert!(compile("{a: .b,}").is_err());
+        assert!(compile("{a: .b}.c").is_err());
+    }
+
+    #[test]
+    pub fn test_comma_and_collect() {
+        let compiled_lex = compile(".metadata.name, .id | [.a, .b[0]]").unwrap();
+        let true_result: LexicalOperations = LinkedList::from([
+            LexOperator::Expression(Expression::Comma(
+                path(&["metadata", "name"]),
+                path(&["id"]),
+            )),
+            Pipe(LinkedList::from([LexOperator::Expression(
+                Expression::Collect(Box::new(Expression::Comma(
+                    path(&["a"]),
+                    Box::new(Expression::Path(
+                        LinkedList::from([
+                            Identifier("b".to_string()),
+                            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                                crate::Slicer::Index(0),
+                            ]))),
+                        ])
+                        .into(),
+                    )),
+                ))),
+            )])),
+        ])
+        .into();
+        assert_eq!(compiled_lex, true_result);
+
+        let cases = [
+            (".a, .b | .c", ".a, .b | .c"),
+            (".a | .b, .c", ".a | .b, .c"),
+            (".a, (.b, .c)", ".a, (.b, .c)"),
+            ("(.a | .b), .c", "(.a | .b), .c"),
+            ("[.spec.containers[*].image]", "[.spec.containers[*].image]"),
+            ("[.a] | .[0]", "[.a] | .[0]"),
+            ("[ ]", ".[*]"),
+            (". | [ ]", ". | []"),
+            ("[{a: .b}, [.c]]", "[{a: .b}, [.c]]"),
+            ("{a: (.b, .c), d: .e | .f}", "{a: (.b, .c), d: (.e | .f)}"),
+            ("[?(.a, .b)]", ".[?(.a, .b)]"),
+            (r".a\,b", r".a\,b"),
+            ("[0], .[..m], .[*]", ".[0], .[..m], .[*]"),
+            ("[..m], [0]", ".[..m], [0]"),
+        ];
+        for (lex, display) in cases {
+            let compiled_lex = compile(lex).unwrap();
+            assert_eq!(compiled_lex.to_string(), display, "{}", lex);
+            assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        }
+
+        assert!(compile(".a,").is_err());
+        assert!(compile("[.a").is_err());
+        assert!(compile("[.a, .b] .c").is_err());
+    }
+
+    #[test]
+    pub fn test_filter_errors() {
+        assert!(compile("[?(.a = 1)]").is_err());
+        assert!(compile("[?(.a == no-pe)]").is_err());
+        assert!(compile(r#"[?(.a | test("x")]"#).is_err());
+        assert!(compile(r#"[?(.a | sub("x" "y"))]"#).is_err());
+        assert!(compile(".a | .b )").is_err());
+        assert!(compile("[?(.a == 1]").is_err());
+        assert!(compile("[?((.a == 1)]").is_err());
+        assert!(compile("[?(.a and)]").is_err());
+        assert!(compile("[?(.a andx .b)]").is_err());
+        assert!(compile(r#"[?(.a == "open)]"#).is_err());
+    }
+}
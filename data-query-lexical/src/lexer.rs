@@ -0,0 +1,1254 @@
+use crate::expression::{
+    continue_expression, expect, expression, quoted_key, skip_whitespace, unexpected, Expression,
+};
+use crate::lexer_constants::*;
+use std::collections::LinkedList;
+use std::fmt::{Debug, Display, Formatter};
+use std::num::ParseIntError;
+use std::ops::{Deref, DerefMut};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum LexerError {
+    EndOfQuery {
+        expected: String,
+        char_pointer: usize,
+        lex: String,
+    },
+    FailedToParseInt(ParseIntError),
+    UnexpectedCharacter {
+        expected: String,
+        found: String,
+        char_pointer: usize,
+        lex: String,
+    },
+}
+
+impl From<ParseIntError> for LexerError {
+    fn from(e: ParseIntError) -> Self {
+        Self::FailedToParseInt(e)
+    }
+}
+
+pub type LexResult<T> = Result<T, LexerError>;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum GenericObjectIndex {
+    Wildcard,
+    Slice(LinkedList<Slicer>),
+    /// `[?(expression)]`: every element for which the expression holds.
+    Filter(Expression),
+    /// `[app.kubernetes.io/*]`: the map keys matching a glob pattern, where `*` matches any
+    /// sequence of characters, `?` a single character and `[a-z]` or `[!a-z]` a character class.
+    /// The pattern is kept as written, so `\` escapes the character that follows it.
+    Glob(String),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Slicer {
+    Index(usize),
+    /// Inclusive range of indices. Negative bounds count from the end of the array, where `-1`
+    /// is the last element, and a missing end leaves the range open towards the end.
+    Slice(isize, Option<isize>),
+    /// Python style `start:end:step` slice, where `end` is exclusive and every bound is
+    /// optional. Negative bounds count from the end and a negative step walks backwards.
+    Step(Option<isize>, Option<isize>, Option<isize>),
+    Ident(String),
+    /// `[a..m]`: the map keys between both bounds, inclusive. A missing bound leaves the range
+    /// open on that side.
+    KeyRange(Option<String>, Option<String>),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum LexOperator {
+    Identifier(String),
+    Pipe(LinkedList<LexOperator>),
+    Generic(GenericObjectIndex),
+    /// `..key` or `..[index]`: applies the operator to the current node and every node below
+    /// it, in document order.
+    RecursiveDescent(Box<LexOperator>),
+    /// `.key?` or `[index]?`: a segment that yields nothing, rather than failing, when the key or
+    /// index is missing or does not apply to the value.
+    Optional(Box<LexOperator>),
+    /// Anything following a pipe that is not a plain path, such as a function call. Every value
+    /// the expression produces continues down the remaining operations.
+    Expression(Expression),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct LexicalOperations(pub(crate) LinkedList<LexOperator>);
+
+impl From<LinkedList<LexOperator>> for LexicalOperations {
+    fn from(v: LinkedList<LexOperator>) -> Self {
+        Self(v)
+    }
+}
+
+impl From<&LexicalOperations> for LexicalOperations {
+    fn from(v: &LexicalOperations) -> Self {
+        v.clone()
+    }
+}
+
+impl Deref for LexicalOperations {
+    type Target = LinkedList<LexOperator>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for LexicalOperations {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Characters escaped in a key range bound.
+const KEY_RANGE_SPECIAL: &[char] = &[
+    LEX_IDENTIFIER,
+    LEX_GENERIC_SEPARATOR,
+    LEX_GENERIC_SLICE,
+    LEX_GENERIC_END,
+    LEX_GENERIC_FILTER,
+    LEX_STRING_QUOTE,
+    LEX_GLOB_ANY,
+    LEX_GLOB_CLASS_START,
+];
+
+/// Writes `s` with every character in `special` escaped, so the lexer reads it back verbatim.
+fn write_escaped(f: &mut Formatter<'_>, s: &str, special: &[char]) -> std::fmt::Result {
+    for c in s.chars() {
+        if c == LEX_ESCAPE || c == LEX_ROUGE_WIDESPACE || special.contains(&c) {
+            write!(f, "{}", LEX_ESCAPE)?;
+        }
+        write!(f, "{}", c)?;
+    }
+    Ok(())
+}
+
+impl Display for Slicer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Slicer::Index(i) => write!(f, "{}", i),
+            Slicer::Slice(from, Some(to)) => write!(f, "{}{}{}", from, LEX_GENERIC_SLICE, to),
+            Slicer::Slice(from, None) if *from < 0 => write!(f, "{}", from),
+            Slicer::Slice(from, None) => write!(f, "{}{}", from, LEX_GENERIC_SLICE),
+            Slicer::Step(start, end, step) => {
+                let bound = |b: &Option<isize>| b.map(|b| b.to_string()).unwrap_or_default();
+                write!(f, "{}{}{}", bound(start), LEX_GENERIC_STEP, bound(end))?;
+                if let Some(step) = step {
+                    write!(f, "{}{}", LEX_GENERIC_STEP, step)?;
+                }
+                Ok(())
+            }
+            Slicer::KeyRange(from, to) => {
+                let bound = |f: &mut Formatter<'_>, b: &Option<String>| match b {
+                    Some(b) if b.is_empty() => {
+                        write!(f, "{}{}", LEX_STRING_QUOTE, LEX_STRING_QUOTE)
+                    }
+                    Some(b) => write_escaped(f, b, KEY_RANGE_SPECIAL),
+                    None => Ok(()),
+                };
+                bound(f, from)?;
+                write!(f, "{}", LEX_KEY_RANGE)?;
+                bound(f, to)
+            }
+            // Keys that would read back as an index, a slice or a wildcard are quoted.
+            Slicer::Ident(ident)
+                if ident.is_empty()
+                    || ident == LEX_GENERIC_WILDCARD
+                    || ident.parse::<isize>().is_ok()
+                    || ident.contains(LEX_KEY_RANGE)
+                    || matches!(step_slicer(ident, 0), Ok(Some(_)) | Err(_)) =>
+            {
+                write!(f, "{}", serde_json::Value::String(ident.to_string()))
+            }
+            Slicer::Ident(ident) => write_escaped(
+                f,
+                ident,
+                &[
+                    LEX_GENERIC_SEPARATOR,
+                    LEX_GENERIC_SLICE,
+                    LEX_GENERIC_END,
+                    LEX_GENERIC_FILTER,
+                    LEX_STRING_QUOTE,
+                    LEX_GLOB_ANY,
+                    LEX_GLOB_CLASS_START,
+                ],
+            ),
+        }
+    }
+}
+
+impl Display for GenericObjectIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", LEX_GENERIC_START)?;
+        match self {
+            GenericObjectIndex::Wildcard => write!(f, "{}", LEX_GENERIC_WILDCARD)?,
+            GenericObjectIndex::Slice(slice) => {
+                for (i, s) in slice.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", LEX_GENERIC_SEPARATOR)?;
+                    }
+                    write!(f, "{}", s)?;
+                }
+            }
+            GenericObjectIndex::Filter(e) => write!(
+                f,
+                "{}{}{}{}",
+                LEX_GENERIC_FILTER, LEX_CAPSULE_START, e, LEX_CAPSULE_END
+            )?,
+            GenericObjectIndex::Glob(pattern) => write!(f, "{}", pattern)?,
+        }
+        write!(f, "{}", LEX_GENERIC_END)
+    }
+}
+
+impl Display for LexOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexOperator::Identifier(ident) if ident.is_empty() => {
+                write!(
+                    f,
+                    "{}{}{}",
+                    LEX_IDENTIFIER, LEX_STRING_QUOTE, LEX_STRING_QUOTE
+                )
+            }
+            LexOperator::Identifier(ident) => {
+                write!(f, "{}", LEX_IDENTIFIER)?;
+                write_escaped(f, ident, LEX_EXPRESSION_DELIMITERS)
+            }
+            LexOperator::Pipe(p) => {
+                write!(f, " {} ", LEX_PIPE)?;
+                write_operations(f, p)
+            }
+            LexOperator::Generic(g) => write!(f, "{}", g),
+            LexOperator::RecursiveDescent(o) => match o.as_ref() {
+                LexOperator::Generic(_) => write!(f, "{}{}{}", LEX_IDENTIFIER, LEX_IDENTIFIER, o),
+                _ => write!(f, "{}{}", LEX_IDENTIFIER, o),
+            },
+            LexOperator::Optional(o) => write!(f, "{}{}", o, LEX_OPTIONAL),
+            LexOperator::Expression(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn write_operations(
+    f: &mut Formatter<'_>,
+    operators: &LinkedList<LexOperator>,
+) -> std::fmt::Result {
+    match operators.front() {
+        None
+        | Some(LexOperator::Pipe(_))
+        | Some(LexOperator::Generic(_))
+        | Some(LexOperator::Optional(_)) => write!(f, "{}", LEX_IDENTIFIER)?,
+        _ => {}
+    }
+    for operator in operators {
+        write!(f, "{}", operator)?;
+    }
+    Ok(())
+}
+
+/// Renders the operations back into their canonical query string, which compiles to the same
+/// operations again.
+impl Display for LexicalOperations {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_operations(f, self)
+    }
+}
+
+impl TryInto<LexicalOperations> for &str {
+    type Error = String;
+
+    fn try_into(self) -> Result<LexicalOperations, String> {
+        compile(self).map_err(|e| format!("{:?}", e))
+    }
+}
+
+pub fn compile(s: &str) -> LexResult<LexicalOperations> {
+    let mut lexer_vec = s.chars().collect::<Vec<char>>();
+    lexer_vec.reverse();
+    generic_compiler(
+        &mut lexer_vec,
+        &mut Default::default(),
+        Default::default(),
+        false,
+        Default::default(),
+    )
+    .map(LexicalOperations::from)
+}
+
+pub fn generic_compiler(
+    lexer_vec: &mut Vec<char>,
+    operator: &mut LinkedList<LexOperator>,
+    mut collect: String,
+    mut escape: bool,
+    mut char_pointer: usize,
+) -> LexResult<LinkedList<LexOperator>> {
+    let char = lexer_vec.pop();
+    if let Some(c) = char {
+        char_pointer += 1;
+        if !escape {
+            match c {
+                LEX_ESCAPE => {
+                    escape = true;
+                }
+                LEX_IDENTIFIER => {
+                    if !collect.is_empty() {
+                        operator.push_back(LexOperator::Identifier(collect));
+                    }
+                    collect = Default::default();
+                    if lexer_vec.last() == Some(&LEX_IDENTIFIER) {
+                        lexer_vec.pop();
+                        char_pointer += 1;
+                        let (segment, pointer) = recursive_descent(lexer_vec, char_pointer)?;
+                        operator.push_back(LexOperator::RecursiveDescent(Box::new(segment)));
+                        char_pointer = pointer;
+                    }
+                }
+                // A query starting with an object construction, a collection or a group, as in
+                // `{name: .metadata.name}`, `[.items[*].name]` or `(.a | .b), .c`, is parsed as
+                // an expression.
+                LEX_OBJECT_START | LEX_GENERIC_START | LEX_CAPSULE_START
+                    if operator.is_empty()
+                        && collect.is_empty()
+                        && (c != LEX_GENERIC_START || starts_collection(lexer_vec)) =>
+                {
+                    lexer_vec.push(c);
+                    char_pointer -= 1;
+                    let e = expression(lexer_vec, &mut char_pointer)?;
+                    return end_of_query(e, lexer_vec, char_pointer);
+                }
+                // So is a query starting with a function call, as in `path(..containerPort)`.
+                c if operator.is_empty()
+                    && collect.is_empty()
+                    && starts_function_call(c, lexer_vec) =>
+                {
+                    lexer_vec.push(c);
+                    char_pointer -= 1;
+                    let e = expression(lexer_vec, &mut char_pointer)?;
+                    return end_of_query(e, lexer_vec, char_pointer);
+                }
+                LEX_GENERIC_START => {
+                    if !collect.is_empty() {
+                        operator.push_back(LexOperator::Identifier(collect));
+                        collect = Default::default();
+                    }
+                    let v = generic_object_index(
+                        lexer_vec,
+                        Default::default(),
+                        LinkedList::new(),
+                        None,
+                        false,
+                        char_pointer,
+                    )?;
+                    operator.push_back(LexOperator::Generic(v));
+                }
+                // A quoted key, as in `."prometheus.io/port"`, is taken verbatim.
+                LEX_STRING_QUOTE if collect.is_empty() => {
+                    lexer_vec.push(c);
+                    char_pointer -= 1;
+                    let key = quoted_key(lexer_vec, &mut char_pointer)?;
+                    operator.push_back(LexOperator::Identifier(key));
+                }
+                // `.name, .id`: the path read so far is the first of several expressions.
+                LEX_GENERIC_SEPARATOR => {
+                    if !collect.is_empty() {
+                        operator.push_back(LexOperator::Identifier(collect));
+                    }
+                    lexer_vec.push(c);
+                    char_pointer -= 1;
+                    let first = Expression::Path(std::mem::take(operator).into());
+                    let e = continue_expression(first, lexer_vec, &mut char_pointer)?;
+                    return end_of_query(e, lexer_vec, char_pointer);
+                }
+                LEX_OPTIONAL => {
+                    if !collect.is_empty() {
+                        operator.push_back(LexOperator::Identifier(collect));
+                        collect = Default::default();
+                    }
+                    optional(operator, char_pointer)?;
+                }
+                LEX_PIPE => {
+                    if !collect.is_empty() {
+                        operator.push_back(LexOperator::Identifier(collect));
+                    }
+                    // The right hand side of a pipe may be any expression, such as a function
+                    // call, so the remaining query is handed to the expression parser.
+                    let pipe = expression(lexer_vec, &mut char_pointer)?;
+                    operator.push_back(LexOperator::Pipe(end_of_query(
+                        pipe,
+                        lexer_vec,
+                        char_pointer,
+                    )?));
+                    return Ok(operator.clone());
+                }
+                _ => {
+                    if c != LEX_ROUGE_WIDESPACE {
+                        collect.push(c)
+                    }
+                }
+            }
+        } else {
+            collect.push(c);
+            escape = false;
+        }
+        generic_compiler(lexer_vec, operator, collect, escape, char_pointer)
+    } else {
+        if !collect.is_empty() {
+            operator.push_back(LexOperator::Identifier(collect));
+        }
+        Ok(operator.clone())
+    }
+}
+
+/// Whether a `[` at the start of the query collects the results of an expression, as in
+/// `[.items[*].name]`, rather than indexing the input, as in `[0]` or `[..m]`.
+fn starts_collection(lexer_vec: &[char]) -> bool {
+    let mut rest = lexer_vec.iter().rev().skip_while(|c| c.is_whitespace());
+    match rest.next() {
+        Some(&LEX_IDENTIFIER) => rest.next() != Some(&LEX_IDENTIFIER),
+        Some(&LEX_OBJECT_START) | Some(&LEX_CAPSULE_START) => true,
+        _ => false,
+    }
+}
+
+/// Whether the query goes on with a function call, a name followed by `(`, rather than a key.
+fn starts_function_call(c: char, lexer_vec: &[char]) -> bool {
+    let mut rest = lexer_vec
+        .iter()
+        .rev()
+        .skip_while(|c| c.is_alphanumeric() || **c == '_');
+    c.is_alphabetic() && rest.next() == Some(&LEX_CAPSULE_START)
+}
+
+/// Turns an expression that must span the rest of the query into operations.
+fn end_of_query(
+    e: Expression,
+    lexer_vec: &[char],
+    char_pointer: usize,
+) -> LexResult<LinkedList<LexOperator>> {
+    if !lexer_vec.is_empty() {
+        return Err(unexpected("End of query", lexer_vec, char_pointer));
+    }
+    Ok(e.into_operations())
+}
+
+/// Marks the last segment as optional, as in `.valueFrom?` or `.ports[0]?`.
+pub(crate) fn optional(
+    operator: &mut LinkedList<LexOperator>,
+    char_pointer: usize,
+) -> LexResult<()> {
+    match operator.pop_back() {
+        Some(o) => {
+            operator.push_back(LexOperator::Optional(Box::new(o)));
+            Ok(())
+        }
+        None => Err(LexerError::UnexpectedCharacter {
+            expected: "Identifier/Generic".to_string(),
+            found: LEX_OPTIONAL.to_string(),
+            char_pointer,
+            lex: String::new(),
+        }),
+    }
+}
+
+/// Parses the segment following `..`, which is either an identifier, `*` or a generic index.
+pub(crate) fn recursive_descent(
+    lexer_vec: &mut Vec<char>,
+    mut char_pointer: usize,
+) -> LexResult<(LexOperator, usize)> {
+    let mut collect = String::new();
+    let mut escape = false;
+    while let Some(c) = lexer_vec.last().copied() {
+        match c {
+            _ if escape => {
+                collect.push(c);
+                escape = false;
+            }
+            LEX_ESCAPE => escape = true,
+            LEX_ROUGE_WIDESPACE if collect.is_empty() => {}
+            LEX_STRING_QUOTE if collect.is_empty() => {
+                let key = quoted_key(lexer_vec, &mut char_pointer)?;
+                return Ok((LexOperator::Identifier(key), char_pointer));
+            }
+            LEX_GENERIC_START if collect.is_empty() => {
+                lexer_vec.pop();
+                char_pointer += 1;
+                let generic = generic_object_index(
+                    lexer_vec,
+                    Default::default(),
+                    LinkedList::new(),
+                    None,
+                    false,
+                    char_pointer,
+                )?;
+                return Ok((LexOperator::Generic(generic), char_pointer));
+            }
+            _ if LEX_EXPRESSION_DELIMITERS.contains(&c) || c.is_whitespace() => break,
+            _ => collect.push(c),
+        }
+        lexer_vec.pop();
+        char_pointer += 1;
+    }
+    if collect.is_empty() {
+        Err(LexerError::UnexpectedCharacter {
+            expected: "Identifier/Generic".to_string(),
+            found: lexer_vec.last().map(|c| c.to_string()).unwrap_or_default(),
+            char_pointer,
+            lex: format!("{:?}", lexer_vec),
+        })
+    } else if collect == LEX_GENERIC_WILDCARD {
+        Ok((
+            LexOperator::Generic(GenericObjectIndex::Wildcard),
+            char_pointer,
+        ))
+    } else {
+        Ok((LexOperator::Identifier(collect), char_pointer))
+    }
+}
+
+/// Parses a `start:end:step` entry. Entries that are not made up of integers are not a step
+/// slice, which leaves keys such as `f:metadata` to be matched as identifiers.
+fn step_slicer(collect: &str, char_pointer: usize) -> LexResult<Option<Slicer>> {
+    let mut bounds = Vec::new();
+    for bound in collect.split(LEX_GENERIC_STEP) {
+        if bound.is_empty() {
+            bounds.push(None);
+        } else if let Ok(i) = bound.parse::<isize>() {
+            bounds.push(Some(i));
+        } else {
+            return Ok(None);
+        }
+    }
+    match bounds[..] {
+        [_, _, Some(0)] => Err(LexerError::UnexpectedCharacter {
+            expected: "Non-zero step".to_string(),
+            found: "0".to_string(),
+            char_pointer,
+            lex: collect.to_string(),
+        }),
+        [start, end] => Ok(Some(Slicer::Step(start, end, None))),
+        [start, end, step] => Ok(Some(Slicer::Step(start, end, step))),
+        _ => Ok(None),
+    }
+}
+
+/// Turns the collected entry into a slicer and appends it.
+///
+/// A pending `from` makes the entry an inclusive range, which is open towards the end of the
+/// array when nothing follows the `-`. A lone negative number is an open range over the tail
+/// of the array, so `-2` selects the last two elements and `-1` the last one.
+fn push_slicer(
+    slicer: &mut LinkedList<Slicer>,
+    collect: &str,
+    tmp_slice: Option<isize>,
+    char_pointer: usize,
+) -> LexResult<()> {
+    if let Some(from) = tmp_slice {
+        let to = if collect.is_empty() {
+            None
+        } else {
+            Some(collect.parse::<isize>()?)
+        };
+        slicer.push_back(Slicer::Slice(from, to));
+    } else if let Some(step) = step_slicer(collect, char_pointer)? {
+        slicer.push_back(step);
+    } else if let Ok(u) = collect.parse::<usize>() {
+        slicer.push_back(Slicer::Index(u));
+    } else if let Ok(i) = collect.parse::<isize>() {
+        slicer.push_back(Slicer::Slice(i, None));
+    } else {
+        slicer.push_back(Slicer::Ident(collect.to_string()));
+    }
+    Ok(())
+}
+
+pub(crate) fn generic_object_index(
+    lexer_vec: &mut Vec<char>,
+    mut collect: String,
+    mut slicer: LinkedList<Slicer>,
+    mut tmp_slice: Option<isize>,
+    escape: bool,
+    mut char_pointer: usize,
+) -> LexResult<GenericObjectIndex> {
+    let char = lexer_vec.pop();
+    if let Some(c) = char {
+        char_pointer += 1;
+        if !escape {
+            match c {
+                LEX_ESCAPE => {
+                    generic_object_index(lexer_vec, collect, slicer, tmp_slice, true, char_pointer)
+                }
+                LEX_GENERIC_END => {
+                    if (collect.is_empty() || collect == LEX_GENERIC_WILDCARD)
+                        && slicer.is_empty()
+                        && tmp_slice.is_none()
+                    {
+                        Ok(GenericObjectIndex::Wildcard)
+                    } else if !collect.is_empty() || tmp_slice.is_some() {
+                        push_slicer(&mut slicer, &collect, tmp_slice, char_pointer)?;
+                        Ok(GenericObjectIndex::Slice(slicer))
+                    } else {
+                        Ok(GenericObjectIndex::Slice(slicer))
+                    }
+                }
+                LEX_GENERIC_FILTER
+                    if collect.is_empty()
+                        && tmp_slice.is_none()
+                        && slicer.is_empty()
+                        && lexer_vec.last() == Some(&LEX_CAPSULE_START) =>
+                {
+                    expect(lexer_vec, &mut char_pointer, LEX_CAPSULE_START)?;
+                    let filter = expression(lexer_vec, &mut char_pointer)?;
+                    expect(lexer_vec, &mut char_pointer, LEX_CAPSULE_END)?;
+                    expect(lexer_vec, &mut char_pointer, LEX_GENERIC_END)?;
+                    Ok(GenericObjectIndex::Filter(filter))
+                }
+                // A quoted entry, as in `["helm.sh/chart"]`, is always a key.
+                LEX_STRING_QUOTE if collect.is_empty() && tmp_slice.is_none() => {
+                    lexer_vec.push(c);
+                    char_pointer -= 1;
+                    let key = quoted_key(lexer_vec, &mut char_pointer)?;
+                    skip_whitespace(lexer_vec, &mut char_pointer);
+                    if key_range_start(lexer_vec, &mut char_pointer) {
+                        let to = key_range_bound(lexer_vec, &mut char_pointer)?;
+                        slicer.push_back(Slicer::KeyRange(Some(key), to));
+                    } else {
+                        slicer.push_back(Slicer::Ident(key));
+                    }
+                    next_entry(lexer_vec, slicer, char_pointer)
+                }
+                LEX_IDENTIFIER
+                    if tmp_slice.is_none() && lexer_vec.last() == Some(&LEX_IDENTIFIER) =>
+                {
+                    lexer_vec.pop();
+                    char_pointer += 1;
+                    let from = (!collect.is_empty()).then_some(collect);
+                    let to = key_range_bound(lexer_vec, &mut char_pointer)?;
+                    slicer.push_back(Slicer::KeyRange(from, to));
+                    next_entry(lexer_vec, slicer, char_pointer)
+                }
+                LEX_GENERIC_SEPARATOR => {
+                    if collect.is_empty() && tmp_slice.is_none() && slicer.is_empty() {
+                        Err(LexerError::UnexpectedCharacter {
+                            expected: "Integer/String".to_string(),
+                            found: LEX_GENERIC_SEPARATOR.to_string(),
+                            char_pointer,
+                            lex: format!("{:?}", lexer_vec),
+                        })
+                    } else {
+                        push_slicer(&mut slicer, &collect, tmp_slice, char_pointer)?;
+                        generic_object_index(
+                            lexer_vec,
+                            Default::default(),
+                            slicer,
+                            None,
+                            false,
+                            char_pointer,
+                        )
+                    }
+                }
+                LEX_GENERIC_SLICE => {
+                    if collect.is_empty() || collect.ends_with(LEX_GENERIC_STEP) {
+                        // A `-` that does not follow a number is the sign of an end relative
+                        // index, both at the start and at the end of a range.
+                        collect.push(c);
+                    } else if tmp_slice.is_some() {
+                        return Err(LexerError::UnexpectedCharacter {
+                            expected: format!("{} or {}", LEX_GENERIC_SEPARATOR, LEX_GENERIC_END),
+                            found: LEX_GENERIC_SLICE.to_string(),
+                            char_pointer,
+                            lex: format!("{:?}", lexer_vec),
+                        });
+                    } else if let Ok(i) = collect.parse::<isize>() {
+                        tmp_slice = Some(i);
+                        collect = Default::default();
+                    } else if collect.chars().any(|c| c != LEX_GENERIC_SLICE) {
+                        // Following anything but a number, `-` is part of a key such as
+                        // `pod-template-hash`.
+                        collect.push(c);
+                    } else {
+                        return Err(LexerError::UnexpectedCharacter {
+                            expected: "Integer".to_string(),
+                            found: "String".to_string(),
+                            char_pointer,
+                            lex: format!("{:?}", lexer_vec),
+                        });
+                    }
+                    generic_object_index(lexer_vec, collect, slicer, tmp_slice, false, char_pointer)
+                }
+                LEX_ROUGE_WIDESPACE => {
+                    generic_object_index(lexer_vec, collect, slicer, tmp_slice, false, char_pointer)
+                }
+                // A lone `*` is the wildcard, any other use of a glob character makes the
+                // entry a glob pattern.
+                LEX_GLOB_ANY | LEX_GLOB_ONE | LEX_GLOB_CLASS_START
+                    if !(c == LEX_GLOB_ANY
+                        && collect.is_empty()
+                        && lexer_vec.last() == Some(&LEX_GENERIC_END)) =>
+                {
+                    if !slicer.is_empty() || tmp_slice.is_some() {
+                        return Err(LexerError::UnexpectedCharacter {
+                            expected: "Glob pattern on its own".to_string(),
+                            found: c.to_string(),
+                            char_pointer,
+                            lex: format!("{:?}", lexer_vec),
+                        });
+                    }
+                    lexer_vec.push(c);
+                    glob_pattern(lexer_vec, &collect, char_pointer - 1)
+                }
+                _ => {
+                    collect.push(c);
+                    generic_object_index(lexer_vec, collect, slicer, tmp_slice, false, char_pointer)
+                }
+            }
+        } else {
+            collect.push(c);
+            generic_object_index(lexer_vec, collect, slicer, tmp_slice, false, char_pointer)
+        }
+    } else {
+        Err(LexerError::EndOfQuery {
+            expected: String::from(LEX_GENERIC_END),
+            char_pointer,
+            lex: format!("{:?}", lexer_vec),
+        })
+    }
+}
+
+/// Continues after an entry that has been pushed, which must be followed by `,` or `]`.
+fn next_entry(
+    lexer_vec: &mut Vec<char>,
+    slicer: LinkedList<Slicer>,
+    mut char_pointer: usize,
+) -> LexResult<GenericObjectIndex> {
+    skip_whitespace(lexer_vec, &mut char_pointer);
+    match lexer_vec.pop() {
+        Some(LEX_GENERIC_SEPARATOR) => generic_object_index(
+            lexer_vec,
+            Default::default(),
+            slicer,
+            None,
+            false,
+            char_pointer + 1,
+        ),
+        Some(LEX_GENERIC_END) => Ok(GenericObjectIndex::Slice(slicer)),
+        Some(c) => Err(LexerError::UnexpectedCharacter {
+            expected: format!("{} or {}", LEX_GENERIC_SEPARATOR, LEX_GENERIC_END),
+            found: c.to_string(),
+            char_pointer,
+            lex: format!("{:?}", lexer_vec),
+        }),
+        None => Err(LexerError::EndOfQuery {
+            expected: String::from(LEX_GENERIC_END),
+            char_pointer,
+            lex: format!("{:?}", lexer_vec),
+        }),
+    }
+}
+
+/// Consumes the `..` of a key range, if it comes next.
+fn key_range_start(lexer_vec: &mut Vec<char>, char_pointer: &mut usize) -> bool {
+    if lexer_vec.ends_with(&[LEX_IDENTIFIER, LEX_IDENTIFIER]) {
+        lexer_vec.truncate(lexer_vec.len() - 2);
+        *char_pointer += 2;
+        true
+    } else {
+        false
+    }
+}
+
+/// Reads the upper bound of a key range, quoted or not, leaving the `,` or `]` that ends it.
+fn key_range_bound(
+    lexer_vec: &mut Vec<char>,
+    char_pointer: &mut usize,
+) -> LexResult<Option<String>> {
+    skip_whitespace(lexer_vec, char_pointer);
+    if lexer_vec.last() == Some(&LEX_STRING_QUOTE) {
+        return quoted_key(lexer_vec, char_pointer).map(Some);
+    }
+    let mut bound = String::new();
+    while let Some(c) = lexer_vec.last().copied() {
+        match c {
+            LEX_GENERIC_SEPARATOR | LEX_GENERIC_END => break,
+            LEX_ESCAPE => {
+                lexer_vec.pop();
+                *char_pointer += 1;
+                if let Some(escaped) = lexer_vec.pop() {
+                    *char_pointer += 1;
+                    bound.push(escaped);
+                }
+                continue;
+            }
+            LEX_ROUGE_WIDESPACE => {}
+            _ => bound.push(c),
+        }
+        lexer_vec.pop();
+        *char_pointer += 1;
+    }
+    Ok((!bound.is_empty()).then_some(bound))
+}
+
+/// Reads a glob pattern up to the closing `]`. `prefix` holds the characters read before the
+/// first glob character, which are all literal.
+fn glob_pattern(
+    lexer_vec: &mut Vec<char>,
+    prefix: &str,
+    mut char_pointer: usize,
+) -> LexResult<GenericObjectIndex> {
+    let mut pattern = String::new();
+    for c in prefix.chars() {
+        if [
+            LEX_ESCAPE,
+            LEX_ROUGE_WIDESPACE,
+            LEX_GENERIC_SEPARATOR,
+            LEX_GLOB_ANY,
+            LEX_GLOB_ONE,
+            LEX_GLOB_CLASS_START,
+            LEX_GLOB_CLASS_END,
+        ]
+        .contains(&c)
+        {
+            pattern.push(LEX_ESCAPE);
+        }
+        pattern.push(c);
+    }
+    let mut class = false;
+    while let Some(c) = lexer_vec.pop() {
+        char_pointer += 1;
+        match c {
+            LEX_ESCAPE => {
+                pattern.push(c);
+                if let Some(escaped) = lexer_vec.pop() {
+                    char_pointer += 1;
+                    pattern.push(escaped);
+                }
+            }
+            LEX_GLOB_CLASS_START if !class => {
+                class = true;
+                pattern.push(c);
+            }
+            LEX_GLOB_CLASS_END if class => {
+                class = false;
+                pattern.push(c);
+            }
+            LEX_GENERIC_END => return Ok(GenericObjectIndex::Glob(pattern)),
+            LEX_GENERIC_SEPARATOR if !class => {
+                return Err(LexerError::UnexpectedCharacter {
+                    expected: "Glob pattern on its own".to_string(),
+                    found: c.to_string(),
+                    char_pointer,
+                    lex: format!("{:?}", lexer_vec),
+                })
+            }
+            LEX_ROUGE_WIDESPACE if !class => {}
+            _ => pattern.push(c),
+        }
+    }
+    // The `]` closing the index closes an open character class instead, so the class is what
+    // is left unterminated.
+    let expected = match class {
+        true => format!("{} closing the character class", LEX_GLOB_CLASS_END),
+        false => String::from(LEX_GENERIC_END),
+    };
+    Err(LexerError::EndOfQuery {
+        expected,
+        char_pointer,
+        lex: format!("{:?}", lexer_vec),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::LexOperator::*;
+    use crate::lexer::Slicer::*;
+    use crate::lexer::{
+        compile, generic_compiler, generic_object_index, GenericObjectIndex, LexOperator,
+        LexResult, LexerError, Slicer,
+    };
+    use crate::Expression;
+    use crate::LexicalOperations;
+    use std::collections::LinkedList;
+
+    fn lex_vec(s: &str) -> Vec<char> {
+        s.chars().collect::<Vec<char>>()
+    }
+
+    #[test]
+    pub fn test_slicer() {
+        let mut lex_vec = lex_vec("1,2,4-6,hello]");
+        lex_vec.reverse();
+        let slicer = generic_object_index(
+            &mut lex_vec,
+            "".to_string(),
+            LinkedList::new(),
+            None,
+            false,
+            0usize,
+        );
+        let true_generic_object = GenericObjectIndex::Slice(LinkedList::from([
+            Slicer::Index(1),
+            Slicer::Index(2),
+            Slicer::Slice(4, Some(6)),
+            Ident("hello".to_string()),
+        ]));
+
+        assert_eq!(true_generic_object, slicer.unwrap())
+    }
+
+    #[test]
+    pub fn test_slicer_open_and_negative() {
+        let compiled_lex = compile(".conditions[3-,-2,-1,-3--1,1--2]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("conditions".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Slice(3, None),
+                Slice(-2, None),
+                Slice(-1, None),
+                Slice(-3, Some(-1)),
+                Slice(1, Some(-2)),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        assert!(compile(".conditions[1-2-3]").is_err());
+        assert!(compile(".conditions[1-a-b]").is_err());
+        assert!(compile(".conditions[--1]").is_err());
+    }
+
+    #[test]
+    pub fn test_slicer_step() {
+        let compiled_lex = compile(".samples[::2,1:-1,::-1,-3::,f:metadata]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("samples".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Step(None, None, Some(2)),
+                Step(Some(1), Some(-1), None),
+                Step(None, None, Some(-1)),
+                Step(Some(-3), None, None),
+                Ident("f:metadata".to_string()),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        assert!(compile(".samples[::0]").is_err());
+    }
+
+    #[test]
+    pub fn test_slicer_ident() {
+        let compiled_lex = compile(r".labels[app, pod-template-hash,0,\?x, helm.sh/chart]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("labels".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Ident("app".to_string()),
+                Ident("pod-template-hash".to_string()),
+                Index(0),
+                Ident("?x".to_string()),
+                Ident("helm.sh/chart".to_string()),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        let compiled_lex = compiled_lex.unwrap();
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+    }
+
+    #[test]
+    pub fn test_generic_compiler() {
+        let mut lex_vec = lex_vec(".metadata[1,2,4-6,hello]");
+        lex_vec.reverse();
+        let mut operator = LinkedList::new();
+        let compiled_lex = generic_compiler(
+            &mut lex_vec,
+            &mut operator,
+            Default::default(),
+            false,
+            Default::default(),
+        );
+        let true_result: LexResult<LinkedList<LexOperator>> = Ok(LinkedList::from([
+            Identifier("metadata".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Index(1),
+                Index(2),
+                Slice(4, Some(6)),
+                Ident("hello".to_string()),
+            ]))),
+        ]));
+        assert_eq!(true_result, compiled_lex);
+    }
+
+    #[test]
+    pub fn test_compiler() {
+        let compiled_lex = compile(".metadata[1,2,4-6,hello]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("metadata".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Index(1),
+                Index(2),
+                Slice(4, Some(6)),
+                Ident("hello".to_string()),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+    }
+
+    #[test]
+    pub fn test_lex_pipe() {
+        let compiled_lex = compile(".spec.containers[] | .name");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("spec".to_string()),
+            Identifier("containers".to_string()),
+            Generic(GenericObjectIndex::Wildcard),
+            Pipe(LinkedList::from([Identifier("name".to_string())])),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+    }
+
+    #[test]
+    pub fn test_lex_wildcard() {
+        let compiled_lex = compile(".friends[*].name");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("friends".to_string()),
+            Generic(GenericObjectIndex::Wildcard),
+            Identifier("name".to_string()),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+    }
+
+    #[test]
+    pub fn test_lex_optional() {
+        let compiled_lex = compile(".env[*].valueFrom?.secretKeyRef | .ports[0]?");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("env".to_string()),
+            Generic(GenericObjectIndex::Wildcard),
+            Optional(Box::new(Identifier("valueFrom".to_string()))),
+            Identifier("secretKeyRef".to_string()),
+            Pipe(LinkedList::from([
+                Identifier("ports".to_string()),
+                Optional(Box::new(Generic(GenericObjectIndex::Slice(
+                    LinkedList::from([Slicer::Index(0)]),
+                )))),
+            ])),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        assert_eq!(
+            compiled_lex.unwrap().to_string(),
+            ".env[*].valueFrom?.secretKeyRef | .ports[0]?"
+        );
+        assert_eq!(compile(".[0]?").unwrap().to_string(), ".[0]?");
+        assert_eq!(compile(r".a\?").unwrap().to_string(), r".a\?");
+        assert!(compile("?").is_err());
+        assert!(compile(".a | ?").is_err());
+    }
+
+    #[test]
+    pub fn test_display_round_trip() {
+        for query in [
+            ".metadata[1,2,4-6,hello]",
+            ".conditions[3-,-2,-3--1]",
+            ".samples[::2,1:-1,::-1,:]",
+            "..image | ..[*]",
+            ".meta\\.data[1,2\\,,4-6,hello]",
+            ".spec.containers[*] | .name",
+            ".[0] | .",
+            ".",
+        ] {
+            let compiled_lex = compile(query).unwrap();
+            assert_eq!(query, compiled_lex.to_string());
+            assert_eq!(Ok(compiled_lex.clone()), compile(&compiled_lex.to_string()));
+        }
+    }
+
+    #[test]
+    pub fn test_lex_recursive_descent() {
+        let compiled_lex = compile(".spec..image..[*]..[0,1].name");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("spec".to_string()),
+            RecursiveDescent(Box::new(Identifier("image".to_string()))),
+            RecursiveDescent(Box::new(Generic(GenericObjectIndex::Wildcard))),
+            RecursiveDescent(Box::new(Generic(GenericObjectIndex::Slice(
+                LinkedList::from([Index(0), Index(1)]),
+            )))),
+            Identifier("name".to_string()),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        assert_eq!(compile("..*"), compile("..[*]"));
+        assert!(compile(".spec..").is_err());
+    }
+
+    #[test]
+    pub fn test_lex_escape() {
+        let compiled_lex = compile(".metadata[1,2\\,,4-6,hello]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("metadata".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Index(1),
+                Ident("2,".to_string()),
+                Slice(4, Some(6)),
+                Ident("hello".to_string()),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+    }
+
+    #[test]
+    pub fn test_lex_quoted_key() {
+        let compiled_lex = compile(
+            r#".metadata."prometheus.io/port"["helm.sh/chart", "a\"b", 1, "2"].."\u00e9|x"."#,
+        );
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("metadata".to_string()),
+            Identifier("prometheus.io/port".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Ident("helm.sh/chart".to_string()),
+                Ident("a\"b".to_string()),
+                Index(1),
+                Ident("2".to_string()),
+            ]))),
+            RecursiveDescent(Box::new(Identifier("\u{e9}|x".to_string()))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        let compiled_lex = compiled_lex.unwrap();
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+
+        let compiled_lex = compile(r#"."" | ."a.b" | .["*", "-1", "1:2", ""]"#).unwrap();
+        assert_eq!(
+            compiled_lex.to_string(),
+            r#"."" | .a\.b | .["*","-1","1:2",""]"#
+        );
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        assert!(compile(r#"."open"#).is_err());
+        assert!(compile(r#".["a" b]"#).is_err());
+        assert!(compile(r#".["a""#).is_err());
+    }
+
+    #[test]
+    pub fn test_lex_key_range() {
+        let compiled_lex = compile(r#".ports[8000..15020][a .. m, ..b, "x.y".., "".."z"]"#);
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("ports".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([KeyRange(
+                Some("8000".to_string()),
+                Some("15020".to_string()),
+            )]))),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                KeyRange(Some("a".to_string()), Some("m".to_string())),
+                KeyRange(None, Some("b".to_string())),
+                KeyRange(Some("x.y".to_string()), None),
+                KeyRange(Some("".to_string()), Some("z".to_string())),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        let compiled_lex = compiled_lex.unwrap();
+        assert_eq!(
+            compiled_lex.to_string(),
+            r#".ports[8000..15020][a..m,..b,x\.y..,""..z]"#
+        );
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+
+        let compiled_lex = compile(r#".["a..b", a\.\.b]"#).unwrap();
+        assert_eq!(compiled_lex.to_string(), r#".["a..b","a..b"]"#);
+        assert!(compile(".[a..b").is_err());
+        assert!(compile(".[1-2..3]").is_err());
+    }
+
+    #[test]
+    pub fn test_lex_glob() {
+        let compiled_lex = compile(r".labels[app.kubernetes.io/*].x[v?[0-9]][a\,b*][*]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("labels".to_string()),
+            Generic(GenericObjectIndex::Glob("app.kubernetes.io/*".to_string())),
+            Identifier("x".to_string()),
+            Generic(GenericObjectIndex::Glob("v?[0-9]".to_string())),
+            Generic(GenericObjectIndex::Glob(r"a\,b*".to_string())),
+            Generic(GenericObjectIndex::Wildcard),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+        let compiled_lex = compiled_lex.unwrap();
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+
+        // Quoted and escaped entries stay exact keys.
+        let compiled_lex = compile(r#".["a*"][b\?]"#).unwrap();
+        assert_eq!(compiled_lex.to_string(), r".[a\*][b\?]");
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+        assert!(compile(".[a, b*]").is_err());
+        assert!(compile(".[a*, b]").is_err());
+        assert!(compile(".[a*").is_err());
+        assert!(matches!(
+            compile(".[v[0-9"),
+            Err(LexerError::EndOfQuery { expected, .. }) if expected.contains("character class")
+        ));
+        assert!(matches!(
+            compile(".[v[0-9]"),
+            Err(LexerError::EndOfQuery { expected, .. }) if expected == "]"
+        ));
+    }
+
+    #[test]
+    pub fn test_lex_function_call() {
+        let compiled_lex = compile("path(..containerPort) | length").unwrap();
+        assert_eq!(compiled_lex.to_string(), "path(..containerPort) | length");
+        assert!(matches!(
+            compiled_lex.front(),
+            Some(LexOperator::Expression(Expression::Function(name, _))) if name == "path"
+        ));
+        assert_eq!(compile(&compiled_lex.to_string()).unwrap(), compiled_lex);
+
+        // A name without `(` is still a key.
+        assert_eq!(
+            compile("length").unwrap(),
+            LinkedList::from([Identifier("length".to_string())]).into()
+        );
+        assert!(compile("path(.a").is_err());
+        assert!(compile("path(.a) .b").is_err());
+    }
+
+    #[test]
+    pub fn test_lex_escape_identifier() {
+        let compiled_lex = compile(".meta\\.data[1,2\\,,4-6,hello]");
+        let true_result: LexResult<LexicalOperations> = Ok(LinkedList::from([
+            Identifier("meta.data".to_string()),
+            Generic(GenericObjectIndex::Slice(LinkedList::from([
+                Index(1),
+                Ident("2,".to_string()),
+                Slice(4, Some(6)),
+                Ident("hello".to_string()),
+            ]))),
+        ])
+        .into());
+        assert_eq!(true_result, compiled_lex);
+    }
+}
+
+/*
+
+
+{
+  "default": "Personal",
+  "annotation-field": "annotations",
+  "workspaces": {
+    "Personal": {
+      "HelloWorld": {
+        "annotations": {
+          "my-app.io/group": "HelloWorld"
+        }
+      },
+      "NoWorld": {}
+    }
+  }
+}
+
+
+
+ */
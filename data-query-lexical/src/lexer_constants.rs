@@ -0,0 +1,66 @@
+/// Lexer constant
+pub(crate) const LEX_ROUGE_WIDESPACE: char = ' ';
+pub(crate) const LEX_IDENTIFIER: char = '.';
+pub(crate) const LEX_PIPE: char = '|';
+pub(crate) const LEX_OPTIONAL: char = '?';
+
+pub(crate) const LEX_CAPSULE_START: char = '(';
+pub(crate) const LEX_CAPSULE_END: char = ')';
+pub(crate) const LEX_ARGUMENT_SEPARATOR: char = ';';
+
+pub(crate) const LEX_OBJECT_START: char = '{';
+pub(crate) const LEX_OBJECT_KEY_END: char = ':';
+pub(crate) const LEX_OBJECT_SEPARATOR: char = ',';
+pub(crate) const LEX_OBJECT_END: char = '}';
+
+pub(crate) const LEX_GENERIC_START: char = '[';
+pub(crate) const LEX_GENERIC_SEPARATOR: char = ',';
+pub(crate) const LEX_GENERIC_SLICE: char = '-';
+pub(crate) const LEX_GENERIC_STEP: char = ':';
+pub(crate) const LEX_GENERIC_WILDCARD: &str = "*";
+pub(crate) const LEX_GENERIC_END: char = ']';
+pub(crate) const LEX_GENERIC_FILTER: char = '?';
+pub(crate) const LEX_KEY_RANGE: &str = "..";
+
+pub(crate) const LEX_GLOB_ANY: char = '*';
+pub(crate) const LEX_GLOB_ONE: char = '?';
+pub(crate) const LEX_GLOB_CLASS_START: char = '[';
+pub(crate) const LEX_GLOB_CLASS_END: char = ']';
+
+pub(crate) const LEX_STRING_QUOTE: char = '"';
+pub(crate) const LEX_TRUE: &str = "true";
+pub(crate) const LEX_FALSE: &str = "false";
+pub(crate) const LEX_NULL: &str = "null";
+pub(crate) const LEX_AND: &str = "and";
+pub(crate) const LEX_OR: &str = "or";
+pub(crate) const LEX_NOT: &str = "not";
+
+pub(crate) const LEX_EQUAL: &str = "==";
+pub(crate) const LEX_NOT_EQUAL: &str = "!=";
+pub(crate) const LEX_LESS: &str = "<";
+pub(crate) const LEX_LESS_EQUAL: &str = "<=";
+pub(crate) const LEX_GREATER: &str = ">";
+pub(crate) const LEX_GREATER_EQUAL: &str = ">=";
+pub(crate) const LEX_REGEX_MATCH: &str = "=~";
+
+/// Characters that end an identifier inside an expression.
+pub(crate) const LEX_EXPRESSION_DELIMITERS: &[char] = &[
+    LEX_IDENTIFIER,
+    LEX_PIPE,
+    LEX_CAPSULE_START,
+    LEX_CAPSULE_END,
+    LEX_ARGUMENT_SEPARATOR,
+    LEX_OBJECT_START,
+    LEX_OBJECT_END,
+    LEX_GENERIC_START,
+    LEX_GENERIC_SEPARATOR,
+    LEX_GENERIC_END,
+    LEX_GENERIC_FILTER,
+    LEX_STRING_QUOTE,
+    '=',
+    '!',
+    '<',
+    '>',
+];
+
+pub(crate) const LEX_ESCAPE: char = '\\';
@@ -0,0 +1,234 @@
+mod expression;
+mod lexer;
+pub(crate) mod lexer_constants;
+
+pub use expression::*;
+pub use lexer::*;
+pub use serde_json;
+use serde_json::Value;
+use std::collections::LinkedList;
+
+pub trait MacroFormat {
+    fn macro_fmt(&self) -> String;
+}
+
+impl MacroFormat for Option<isize> {
+    fn macro_fmt(&self) -> String {
+        match self {
+            Some(i) => format!("::std::option::Option::Some({})", i),
+            None => "::std::option::Option::None".to_string(),
+        }
+    }
+}
+
+impl MacroFormat for Option<String> {
+    fn macro_fmt(&self) -> String {
+        match self {
+            Some(s) => format!("::std::option::Option::Some({:?}.into())", s),
+            None => "::std::option::Option::None".to_string(),
+        }
+    }
+}
+
+impl MacroFormat for lexer::Slicer {
+    fn macro_fmt(&self) -> String {
+        match self {
+            Slicer::Index(i) => format!("::data_query_lexical::Slicer::Index({})", i),
+            Slicer::Slice(f, t) => format!(
+                "::data_query_lexical::Slicer::Slice({},{})",
+                f,
+                t.macro_fmt()
+            ),
+            Slicer::Step(start, end, step) => format!(
+                "::data_query_lexical::Slicer::Step({},{},{})",
+                start.macro_fmt(),
+                end.macro_fmt(),
+                step.macro_fmt()
+            ),
+            Slicer::Ident(i) => {
+                format!("::data_query_lexical::Slicer::Ident({:?}.into())", i)
+            }
+            Slicer::KeyRange(from, to) => format!(
+                "::data_query_lexical::Slicer::KeyRange({},{})",
+                from.macro_fmt(),
+                to.macro_fmt()
+            ),
+        }
+    }
+}
+
+impl MacroFormat for LinkedList<lexer::LexOperator> {
+    fn macro_fmt(&self) -> String {
+        format!(
+            "::std::collections::LinkedList::from([{}])",
+            self.iter()
+                .map(|t| t.macro_fmt())
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+}
+
+impl MacroFormat for lexer::LexOperator {
+    fn macro_fmt(&self) -> String {
+        match self {
+            LexOperator::Identifier(i) => {
+                format!(
+                    "::data_query_lexical::LexOperator::Identifier({:?}.into())",
+                    i
+                )
+            }
+            LexOperator::Pipe(p) => {
+                format!("::data_query_lexical::LexOperator::Pipe({})", p.macro_fmt())
+            }
+            LexOperator::Generic(g) => format!(
+                "::data_query_lexical::LexOperator::Generic({})",
+                g.macro_fmt()
+            ),
+            LexOperator::RecursiveDescent(o) => format!(
+                "::data_query_lexical::LexOperator::RecursiveDescent(::std::boxed::Box::new({}))",
+                o.macro_fmt()
+            ),
+            LexOperator::Optional(o) => format!(
+                "::data_query_lexical::LexOperator::Optional(::std::boxed::Box::new({}))",
+                o.macro_fmt()
+            ),
+            LexOperator::Expression(e) => format!(
+                "::data_query_lexical::LexOperator::Expression({})",
+                e.macro_fmt()
+            ),
+        }
+    }
+}
+
+impl MacroFormat for Value {
+    fn macro_fmt(&self) -> String {
+        match self {
+            Value::Null => "::data_query_lexical::serde_json::Value::Null".to_string(),
+            Value::Bool(b) => format!("::data_query_lexical::serde_json::Value::Bool({})", b),
+            Value::Number(n) => {
+                if let Some(u) = n.as_u64() {
+                    format!("::data_query_lexical::serde_json::Value::from({}u64)", u)
+                } else if let Some(i) = n.as_i64() {
+                    format!("::data_query_lexical::serde_json::Value::from({}i64)", i)
+                } else {
+                    format!(
+                        "::data_query_lexical::serde_json::Value::from({:?}f64)",
+                        n.as_f64().unwrap_or_default()
+                    )
+                }
+            }
+            Value::String(s) => format!(
+                "::data_query_lexical::serde_json::Value::String(::std::string::String::from({:?}))",
+                s
+            ),
+            Value::Array(a) => format!(
+                "::data_query_lexical::serde_json::Value::Array(::std::vec![{}])",
+                a.iter().map(|v| v.macro_fmt()).collect::<Vec<String>>().join(",")
+            ),
+            Value::Object(o) => format!(
+                "::data_query_lexical::serde_json::Value::Object(::std::iter::FromIterator::from_iter([{}]))",
+                o.iter()
+                    .map(|(k, v)| format!("(::std::string::String::from({:?}),{})", k, v.macro_fmt()))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+impl MacroFormat for Comparator {
+    fn macro_fmt(&self) -> String {
+        format!("::data_query_lexical::Comparator::{:?}", self)
+    }
+}
+
+impl MacroFormat for Expression {
+    fn macro_fmt(&self) -> String {
+        match self {
+            Expression::Path(p) => format!(
+                "::data_query_lexical::Expression::Path(::data_query_lexical::LexicalOperations::from({}))",
+                p.macro_fmt()
+            ),
+            Expression::Literal(l) => {
+                format!("::data_query_lexical::Expression::Literal({})", l.macro_fmt())
+            }
+            Expression::Compare(l, c, r) => format!(
+                "::data_query_lexical::Expression::Compare(::std::boxed::Box::new({}),{},::std::boxed::Box::new({}))",
+                l.macro_fmt(),
+                c.macro_fmt(),
+                r.macro_fmt()
+            ),
+            Expression::And(l, r) => format!(
+                "::data_query_lexical::Expression::And(::std::boxed::Box::new({}),::std::boxed::Box::new({}))",
+                l.macro_fmt(),
+                r.macro_fmt()
+            ),
+            Expression::Or(l, r) => format!(
+                "::data_query_lexical::Expression::Or(::std::boxed::Box::new({}),::std::boxed::Box::new({}))",
+                l.macro_fmt(),
+                r.macro_fmt()
+            ),
+            Expression::Not(e) => format!(
+                "::data_query_lexical::Expression::Not(::std::boxed::Box::new({}))",
+                e.macro_fmt()
+            ),
+            Expression::Function(name, arguments) => format!(
+                "::data_query_lexical::Expression::Function(::std::string::String::from({:?}),::std::vec![{}])",
+                name,
+                arguments
+                    .iter()
+                    .map(|a| a.macro_fmt())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Expression::Pipe(l, r) => format!(
+                "::data_query_lexical::Expression::Pipe(::std::boxed::Box::new({}),::std::boxed::Box::new({}))",
+                l.macro_fmt(),
+                r.macro_fmt()
+            ),
+            Expression::Comma(l, r) => format!(
+                "::data_query_lexical::Expression::Comma(::std::boxed::Box::new({}),::std::boxed::Box::new({}))",
+                l.macro_fmt(),
+                r.macro_fmt()
+            ),
+            Expression::Collect(e) => format!(
+                "::data_query_lexical::Expression::Collect(::std::boxed::Box::new({}))",
+                e.macro_fmt()
+            ),
+            Expression::Object(entries) => format!(
+                "::data_query_lexical::Expression::Object(::std::vec![{}])",
+                entries
+                    .iter()
+                    .map(|(k, v)| format!("({},{})", k.macro_fmt(), v.macro_fmt()))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+impl MacroFormat for lexer::GenericObjectIndex {
+    fn macro_fmt(&self) -> String {
+        match self {
+            GenericObjectIndex::Wildcard => {
+                "::data_query_lexical::GenericObjectIndex::Wildcard".to_string()
+            }
+            GenericObjectIndex::Slice(s) => format!(
+                "::data_query_lexical::GenericObjectIndex::Slice(::std::collections::LinkedList::from([{}]))",
+                s.iter()
+                    .map(|s| s.macro_fmt())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            GenericObjectIndex::Filter(e) => format!(
+                "::data_query_lexical::GenericObjectIndex::Filter({})",
+                e.macro_fmt()
+            ),
+            GenericObjectIndex::Glob(pattern) => format!(
+                "::data_query_lexical::GenericObjectIndex::Glob({:?}.into())",
+                pattern
+            ),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::function::array_input;
+use crate::ordering::{sort_keys, total_cmp};
+use crate::{Query, QueryError, QueryResult};
+use data_query_lexical::Expression;
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+/// A running total, kept exact while every number is an integer.
+enum Sum {
+    Integer(i128),
+    Float(f64),
+}
+
+impl Sum {
+    fn add(self, n: &Number) -> Self {
+        let integer = n.as_i64().map(i128::from).or(n.as_u64().map(i128::from));
+        match (self, integer) {
+            (Sum::Integer(total), Some(i)) => match total.checked_add(i) {
+                Some(total) => Sum::Integer(total),
+                None => Sum::Float(total as f64 + i as f64),
+            },
+            (Sum::Integer(total), None) => {
+                Sum::Float(total as f64 + n.as_f64().unwrap_or_default())
+            }
+            (Sum::Float(total), _) => Sum::Float(total + n.as_f64().unwrap_or_default()),
+        }
+    }
+
+    /// Integer totals that do not fit in 64 bits turn into floats.
+    fn into_value(self) -> Value {
+        match self {
+            Sum::Integer(total) => match (i64::try_from(total), u64::try_from(total)) {
+                (Ok(i), _) => Value::from(i),
+                (_, Ok(u)) => Value::from(u),
+                _ => Value::from(total as f64),
+            },
+            Sum::Float(total) => Value::from(total),
+        }
+    }
+}
+
+/// `sum`, `min`, `max`, `avg` and `count` over `values`. `sum` and `avg` only take numbers, while
+/// `min` and `max` follow the total ordering of values.
+pub(crate) fn aggregate(name: &str, values: &[Value]) -> QueryResult<Value> {
+    match name {
+        "count" => Ok(Value::from(values.len())),
+        "sum" | "avg" => {
+            let mut sum = Sum::Integer(0);
+            for value in values {
+                match value {
+                    Value::Number(n) => sum = sum.add(n),
+                    _ => {
+                        return Err(QueryError::InvalidFunctionInput(
+                            name.to_string(),
+                            value.clone(),
+                        ))
+                    }
+                }
+            }
+            if name == "sum" {
+                return Ok(sum.into_value());
+            }
+            if values.is_empty() {
+                return Ok(Value::Null);
+            }
+            let total = match sum {
+                Sum::Integer(total) => total as f64,
+                Sum::Float(total) => total,
+            };
+            Ok(Value::from(total / values.len() as f64))
+        }
+        _ => Ok(extreme(name, values).map_or(Value::Null, |i| values[i].clone())),
+    }
+}
+
+/// `min_by(key)` and `max_by(key)`, the element of the input array with the smallest or largest
+/// key. The key of an element is the array of every value `key` produces for it.
+pub(crate) fn extreme_by(
+    compiled: &Query,
+    name: &str,
+    key: &Expression,
+    data: &Value,
+) -> QueryResult<Value> {
+    let elements = array_input(name, data)?;
+    let keys = sort_keys(compiled, key, elements)?;
+    Ok(extreme(name, &keys).map_or(Value::Null, |i| elements[i].clone()))
+}
+
+/// The index of the smallest value for `min` and `min_by`, the first one on ties, or of the
+/// largest value otherwise, the last one on ties.
+fn extreme(name: &str, values: &[Value]) -> Option<usize> {
+    let smallest = name.starts_with("min");
+    let mut best: Option<usize> = None;
+    for (i, value) in values.iter().enumerate() {
+        let Some(b) = best else {
+            best = Some(i);
+            continue;
+        };
+        match total_cmp(value, &values[b]) {
+            Ordering::Less if smallest => best = Some(i),
+            Ordering::Greater | Ordering::Equal if !smallest => best = Some(i),
+            _ => {}
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aggregate::aggregate;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_aggregate_precision() {
+        let values = |v: Value| v.as_array().unwrap().clone();
+        assert_eq!(
+            aggregate("sum", &values(json!([1, 2, 3]))).unwrap(),
+            json!(6)
+        );
+        assert_eq!(aggregate("sum", &values(json!([-1, 2]))).unwrap(), json!(1));
+        assert_eq!(
+            aggregate("sum", &values(json!([1, 0.5]))).unwrap(),
+            json!(1.5)
+        );
+        assert_eq!(
+            aggregate("sum", &values(json!([u64::MAX, 1, -2]))).unwrap(),
+            json!(u64::MAX - 1)
+        );
+        assert_eq!(
+            aggregate("sum", &values(json!([u64::MAX, u64::MAX]))).unwrap(),
+            json!(u64::MAX as f64 * 2.0)
+        );
+        assert_eq!(aggregate("sum", &[]).unwrap(), json!(0));
+        assert_eq!(
+            aggregate("avg", &values(json!([1, 2]))).unwrap(),
+            json!(1.5)
+        );
+        assert_eq!(
+            aggregate("avg", &values(json!([2, 2]))).unwrap(),
+            json!(2.0)
+        );
+        assert_eq!(aggregate("avg", &[]).unwrap(), Value::Null);
+        assert_eq!(
+            aggregate("min", &values(json!([3, 1.5, 2]))).unwrap(),
+            json!(1.5)
+        );
+        assert_eq!(
+            aggregate("max", &values(json!(["b", "c", "a"]))).unwrap(),
+            json!("c")
+        );
+        assert_eq!(aggregate("max", &[]).unwrap(), Value::Null);
+        assert_eq!(
+            aggregate("max", &values(json!([1, "a", null]))).unwrap(),
+            json!("a")
+        );
+        assert!(aggregate("sum", &values(json!([1, "a"]))).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::data_query_lexical::LexerError;
 use crate::QueryError::*;
-use jq_rs::Error;
+use std::convert::Infallible;
 
 #[derive(Debug)]
 pub enum QueryError {
@@ -8,8 +8,35 @@ pub enum QueryError {
     SerdeError(serde_json::Error),
     LexicalError(LexerError),
     CannotUseIdentifierAsArrayKeyIndex(String),
+    /// An object on the path does not have the key.
+    KeyNotFound(String),
+    /// An array on the path is shorter than the index.
+    IndexOutOfRange(usize),
+    /// A value on the path, of the type named first, is neither an object nor an array, so it
+    /// cannot be indexed by the segment, as written.
+    CannotIndex(String, String),
     UncontrolledError(String),
+    /// A query expected to produce a result produced none.
+    NoResults,
+    /// A query expected to produce at most one result produced more.
+    MultipleResults,
     JqError(jq_rs::Error),
+    /// A function that does not exist, or does not take that many arguments, as `name/arity`.
+    UnknownFunction(String),
+    /// A function, named first, was given an input or argument of a type it cannot handle.
+    InvalidFunctionInput(String, serde_json::Value),
+    /// An object construction produced a key that is not a string.
+    InvalidObjectKey(serde_json::Value),
+    /// An expression, as written, whose results are computed rather than found in the document,
+    /// so they have neither a path nor a reference into it.
+    InvalidPathExpression(String),
+    /// A result could not be deserialized into the requested type. The path of the result is
+    /// only known when it was found in the document rather than computed.
+    InvalidResultType(Option<crate::Path>, serde_json::Error),
+    RegexError(regex::Error),
+    InvalidRegexFlags(String),
+    /// A glob pattern with a character class missing its closing `]`.
+    InvalidGlobPattern(String),
 }
 
 impl From<jq_rs::Error> for QueryError {
@@ -18,6 +45,18 @@ impl From<jq_rs::Error> for QueryError {
     }
 }
 
+impl From<Infallible> for QueryError {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+impl From<regex::Error> for QueryError {
+    fn from(e: regex::Error) -> Self {
+        RegexError(e)
+    }
+}
+
 impl From<serde_json::Error> for QueryError {
     fn from(e: serde_json::Error) -> Self {
         SerdeError(e)
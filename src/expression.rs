@@ -0,0 +1,207 @@
+use crate::function::{call_function, regex_matches};
+use crate::ordering::total_cmp;
+use crate::{query_processor, MissingPath, Path, Query, QueryError, QueryResult, Results};
+use data_query_lexical::{Comparator, Expression, LexOperator};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// Evaluates `expression` against `data`, returning every value it produces.
+pub(crate) fn evaluate_expression(
+    compiled: &Query,
+    expression: &Expression,
+    data: &Value,
+) -> QueryResult<Vec<Value>> {
+    match expression {
+        Expression::Path(path) => {
+            let mut results = Results::new(None);
+            query_processor(compiled, data, path.iter(), &mut results, 0)?;
+            Ok(results.into_values())
+        }
+        Expression::Literal(literal) => Ok(vec![literal.clone()]),
+        Expression::Compare(lhs, comparator, rhs) => {
+            let lhs = evaluate_expression(compiled, lhs, data)?;
+            let rhs = evaluate_expression(compiled, rhs, data)?;
+            let mut results = Vec::with_capacity(lhs.len() * rhs.len());
+            for l in &lhs {
+                for r in &rhs {
+                    let matched = match comparator {
+                        Comparator::RegexMatch => regex_matches(compiled, l, r)?,
+                        _ => compare(l, *comparator, r),
+                    };
+                    results.push(Value::Bool(matched));
+                }
+            }
+            Ok(results)
+        }
+        // `and` and `or` short-circuit: the right hand side is only evaluated for the left hand
+        // results that do not already decide the outcome.
+        Expression::And(lhs, rhs) => {
+            let mut results = Vec::new();
+            for l in evaluate_expression(compiled, lhs, data)? {
+                if is_truthy(&l) {
+                    for r in evaluate_expression(compiled, rhs, data)? {
+                        results.push(Value::Bool(is_truthy(&r)));
+                    }
+                } else {
+                    results.push(Value::Bool(false));
+                }
+            }
+            Ok(results)
+        }
+        Expression::Or(lhs, rhs) => {
+            let mut results = Vec::new();
+            for l in evaluate_expression(compiled, lhs, data)? {
+                if is_truthy(&l) {
+                    results.push(Value::Bool(true));
+                } else {
+                    for r in evaluate_expression(compiled, rhs, data)? {
+                        results.push(Value::Bool(is_truthy(&r)));
+                    }
+                }
+            }
+            Ok(results)
+        }
+        Expression::Not(e) => Ok(evaluate_expression(compiled, e, data)?
+            .iter()
+            .map(|v| Value::Bool(!is_truthy(v)))
+            .collect()),
+        Expression::Function(name, arguments) => call_function(compiled, name, arguments, data),
+        Expression::Pipe(lhs, rhs) => {
+            let mut results = Vec::new();
+            for l in evaluate_expression(compiled, lhs, data)? {
+                results.extend(evaluate_expression(compiled, rhs, &l)?);
+            }
+            Ok(results)
+        }
+        Expression::Comma(lhs, rhs) => {
+            let mut results = evaluate_expression(compiled, lhs, data)?;
+            results.extend(evaluate_expression(compiled, rhs, data)?);
+            Ok(results)
+        }
+        Expression::Collect(e) => Ok(vec![Value::Array(evaluate_expression(compiled, e, data)?)]),
+        // Every entry multiplies the objects built so far by its keys and values, so the
+        // first entry varies slowest.
+        Expression::Object(entries) => {
+            let mut objects = vec![Map::new()];
+            for (key, value) in entries {
+                let keys = evaluate_expression(compiled, key, data)?;
+                let values = evaluate_expression(compiled, value, data)?;
+                let mut expanded = Vec::with_capacity(objects.len() * keys.len() * values.len());
+                for object in &objects {
+                    for key in &keys {
+                        let Value::String(key) = key else {
+                            return Err(QueryError::InvalidObjectKey(key.clone()));
+                        };
+                        for value in &values {
+                            let mut object = object.clone();
+                            object.insert(key.clone(), value.clone());
+                            expanded.push(object);
+                        }
+                    }
+                }
+                objects = expanded;
+            }
+            Ok(objects.into_iter().map(Value::Object).collect())
+        }
+    }
+}
+
+/// Evaluates an expression made of paths, pipes and commas against `data`, returning the values
+/// of `data` it selects. Their paths are only tracked `with_paths`, and are empty otherwise.
+/// Other expressions compute values that are not part of `data` and fail with
+/// `QueryError::InvalidPathExpression`.
+pub(crate) fn select_expression<'a>(
+    compiled: &Query,
+    expression: &Expression,
+    data: &'a Value,
+    with_paths: bool,
+) -> QueryResult<Vec<(Path, &'a Value)>> {
+    match expression {
+        Expression::Path(path) => {
+            let mut results = Results::new(None).borrowed();
+            if with_paths {
+                results = results.with_paths();
+            }
+            query_processor(compiled, data, path.iter(), &mut results, 0)?;
+            Ok(results.into_paths())
+        }
+        Expression::Pipe(lhs, rhs) => {
+            let mut results = Vec::new();
+            for (l_path, l) in select_expression(compiled, lhs, data, with_paths)? {
+                for (r_path, r) in select_expression(compiled, rhs, l, with_paths)? {
+                    let mut path = l_path.segments().to_vec();
+                    path.extend_from_slice(r_path.segments());
+                    results.push((path.into(), r));
+                }
+            }
+            Ok(results)
+        }
+        Expression::Comma(lhs, rhs) => {
+            let mut results = select_expression(compiled, lhs, data, with_paths)?;
+            results.extend(select_expression(compiled, rhs, data, with_paths)?);
+            Ok(results)
+        }
+        _ => Err(QueryError::InvalidPathExpression(expression.to_string())),
+    }
+}
+
+/// Whether `select_expression` selects the results of `expression` rather than failing, which is
+/// the case when it is made of paths, pipes and commas only.
+pub(crate) fn is_selection(expression: &Expression) -> bool {
+    match expression {
+        Expression::Path(path) => path.iter().all(is_selection_operator),
+        Expression::Pipe(lhs, rhs) | Expression::Comma(lhs, rhs) => {
+            is_selection(lhs) && is_selection(rhs)
+        }
+        _ => false,
+    }
+}
+
+fn is_selection_operator(operator: &LexOperator) -> bool {
+    match operator {
+        LexOperator::Expression(e) => is_selection(e),
+        LexOperator::Pipe(p) => p.iter().all(is_selection_operator),
+        LexOperator::RecursiveDescent(o) | LexOperator::Optional(o) => is_selection_operator(o),
+        LexOperator::Identifier(_) | LexOperator::Generic(_) => true,
+    }
+}
+
+/// Whether `data` passes a `[?(...)]` filter, which is the case when any value produced by the
+/// expression is truthy.
+pub(crate) fn filter_matches(
+    compiled: &Query,
+    expression: &Expression,
+    data: &Value,
+) -> QueryResult<bool> {
+    // A relative path that does not exist on the element under test reads as `null`, whatever
+    // the options of the query, so predicates such as `.valueFrom == null` can test for missing
+    // keys. Everywhere else paths follow the options.
+    let values = if compiled.options().missing() == MissingPath::Null {
+        evaluate_expression(compiled, expression, data)?
+    } else {
+        let options = compiled.options().with_missing(MissingPath::Null);
+        evaluate_expression(&compiled.clone().with_options(options), expression, data)?
+    };
+    Ok(values.iter().any(is_truthy))
+}
+
+/// Only `null` and `false` are falsy, every other value is truthy.
+pub(crate) fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+/// Compares values in the order of `sort`, so values of different types compare by type, as in
+/// `null < false`, and numbers compare by value regardless of their representation.
+fn compare(lhs: &Value, comparator: Comparator, rhs: &Value) -> bool {
+    let ordering = total_cmp(lhs, rhs);
+    match comparator {
+        Comparator::Equal => ordering == Ordering::Equal,
+        Comparator::NotEqual => ordering != Ordering::Equal,
+        Comparator::Less => ordering == Ordering::Less,
+        Comparator::LessOrEqual => ordering != Ordering::Greater,
+        Comparator::Greater => ordering == Ordering::Greater,
+        Comparator::GreaterOrEqual => ordering != Ordering::Less,
+        // Regular expressions are matched by `regex_matches`.
+        Comparator::RegexMatch => false,
+    }
+}
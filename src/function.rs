@@ -0,0 +1,367 @@
+use crate::aggregate::{aggregate, extreme_by};
+use crate::expression::{evaluate_expression, select_expression};
+use crate::group::{count_by, group_by, to_object};
+use crate::ordering::{reverse, sort};
+use crate::query::{walk_operations, Visitor};
+use crate::{Query, QueryError, QueryResult};
+use data_query_lexical::{Comparator, Expression, LexOperator};
+use regex::{Captures, Regex, RegexBuilder};
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::collections::{HashMap, LinkedList};
+
+/// Regular expressions compiled together with the query, keyed by pattern and then by flags, so
+/// patterns written as literals are not rebuilt for every element they are matched against, and
+/// looking one up does not allocate. Patterns computed from the data are compiled when they are
+/// used.
+#[derive(Debug, Default)]
+pub(crate) struct RegexCache {
+    regexes: HashMap<String, HashMap<String, Regex>>,
+}
+
+impl RegexCache {
+    /// Compiles every literal pattern used by `operations`. Invalid patterns are left out and
+    /// reported when the query is evaluated.
+    pub(crate) fn new(operations: &LinkedList<LexOperator>) -> Self {
+        let mut cache = Self::default();
+        walk_operations(operations, &mut cache);
+        cache
+    }
+
+    pub(crate) fn get(&self, pattern: &str, flags: &str) -> QueryResult<Cow<'_, Regex>> {
+        match self.regexes.get(pattern).and_then(|r| r.get(flags)) {
+            Some(regex) => Ok(Cow::Borrowed(regex)),
+            None => build_regex(pattern, flags).map(Cow::Owned),
+        }
+    }
+
+    fn insert(&mut self, pattern: &Expression, flags: Option<&Expression>) {
+        let flags = match flags {
+            None => "",
+            Some(Expression::Literal(Value::String(flags))) => flags,
+            Some(_) => return,
+        };
+        if let Expression::Literal(Value::String(pattern)) = pattern {
+            if let Ok(regex) = build_regex(pattern, flags) {
+                self.regexes
+                    .entry(pattern.to_string())
+                    .or_default()
+                    .insert(flags.to_string(), regex);
+            }
+        }
+    }
+}
+
+impl Visitor for RegexCache {
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Compare(_, Comparator::RegexMatch, pattern) => self.insert(pattern, None),
+            Expression::Function(name, arguments) => {
+                if let Some((pattern, _, flags)) = regex_arguments(name, arguments) {
+                    self.insert(pattern, flags);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Builds a regular expression with jq style flags: `i` ignores case, `x` allows whitespace and
+/// comments in the pattern, `s` lets `.` match newlines and `m` lets `^` and `$` match at line
+/// breaks. The `g` and `n` flags change how matches are collected and do not affect the pattern.
+fn build_regex(pattern: &str, flags: &str) -> QueryResult<Regex> {
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.chars() {
+        match flag {
+            'g' | 'n' => {}
+            'i' => {
+                builder.case_insensitive(true);
+            }
+            'x' => {
+                builder.ignore_whitespace(true);
+            }
+            's' => {
+                builder.dot_matches_new_line(true);
+            }
+            'm' => {
+                builder.multi_line(true);
+            }
+            _ => return Err(QueryError::InvalidRegexFlags(flags.to_string())),
+        }
+    }
+    builder.build().map_err(QueryError::from)
+}
+
+/// Splits the arguments of a regex function into the pattern, the replacement and the flags.
+fn regex_arguments<'a>(
+    name: &str,
+    arguments: &'a [Expression],
+) -> Option<(
+    &'a Expression,
+    Option<&'a Expression>,
+    Option<&'a Expression>,
+)> {
+    match (name, arguments) {
+        ("test" | "match" | "capture", [pattern]) => Some((pattern, None, None)),
+        ("test" | "match" | "capture", [pattern, flags]) => Some((pattern, None, Some(flags))),
+        ("sub" | "gsub", [pattern, replacement]) => Some((pattern, Some(replacement), None)),
+        ("sub" | "gsub", [pattern, replacement, flags]) => {
+            Some((pattern, Some(replacement), Some(flags)))
+        }
+        _ => None,
+    }
+}
+
+/// Calls the builtin function `name` on `data`, returning every value it produces.
+pub(crate) fn call_function(
+    compiled: &Query,
+    name: &str,
+    arguments: &[Expression],
+    data: &Value,
+) -> QueryResult<Vec<Value>> {
+    if regex_arguments(name, arguments).is_some() {
+        return regex_function(compiled, name, arguments, data);
+    }
+    match (name, arguments) {
+        ("path", [argument]) => Ok(select_expression(compiled, argument, data, true)?
+            .into_iter()
+            .map(|(path, _)| Value::from(path))
+            .collect()),
+        ("length" | "keys" | "values" | "type", []) => introspect(name, data).map(|v| vec![v]),
+        // Aggregations work on the input array, or on every value their argument produces.
+        ("sum" | "min" | "max" | "avg" | "count", []) => {
+            aggregate(name, array_input(name, data)?).map(|v| vec![v])
+        }
+        ("sum" | "min" | "max" | "avg" | "count", [argument]) => {
+            aggregate(name, &evaluate_expression(compiled, argument, data)?).map(|v| vec![v])
+        }
+        ("min_by" | "max_by", [key]) => extreme_by(compiled, name, key, data).map(|v| vec![v]),
+        ("sort" | "unique", []) => sort(compiled, name, None, data).map(|v| vec![v]),
+        ("sort_by" | "unique_by", [key]) => sort(compiled, name, Some(key), data).map(|v| vec![v]),
+        ("reverse", []) => reverse(data).map(|v| vec![v]),
+        ("group_by", [key]) => group_by(compiled, key, data).map(|v| vec![v]),
+        ("count_by", [key]) => count_by(compiled, key, data).map(|v| vec![v]),
+        ("to_object", [key]) => to_object(compiled, key, None, data).map(|v| vec![v]),
+        ("to_object", [key, value]) => to_object(compiled, key, Some(value), data).map(|v| vec![v]),
+        ("has" | "in", [argument]) => evaluate_expression(compiled, argument, data)?
+            .iter()
+            .map(|v| match name {
+                "has" => contains(name, data, v),
+                _ => contains(name, v, data),
+            })
+            .collect(),
+        _ => Err(QueryError::UnknownFunction(format!(
+            "{}/{}",
+            name,
+            arguments.len()
+        ))),
+    }
+}
+
+/// The elements of the input of a function that works on an array.
+pub(crate) fn array_input<'a>(name: &str, data: &'a Value) -> QueryResult<&'a [Value]> {
+    match data {
+        Value::Array(elements) => Ok(elements),
+        _ => Err(QueryError::InvalidFunctionInput(
+            name.to_string(),
+            data.clone(),
+        )),
+    }
+}
+
+/// `length`, `keys`, `values` and `type`, which describe their input.
+fn introspect(name: &str, data: &Value) -> QueryResult<Value> {
+    let invalid = || QueryError::InvalidFunctionInput(name.to_string(), data.clone());
+    match (name, data) {
+        ("length", Value::Null) => Ok(Value::from(0)),
+        // The length of a number is its absolute value.
+        ("length", Value::Number(n)) => Ok(match (n.as_u64(), n.as_i64()) {
+            (Some(_), _) => data.clone(),
+            (None, Some(i)) => Value::from(i.unsigned_abs()),
+            (None, None) => Value::from(n.as_f64().unwrap_or_default().abs()),
+        }),
+        ("length", Value::String(s)) => Ok(Value::from(s.chars().count())),
+        ("length", Value::Array(a)) => Ok(Value::from(a.len())),
+        ("length", Value::Object(m)) => Ok(Value::from(m.len())),
+        // Keys are sorted, and the keys of an array are its indices.
+        ("keys", Value::Object(m)) => {
+            let mut keys = m.keys().cloned().collect::<Vec<_>>();
+            keys.sort();
+            Ok(Value::from(keys))
+        }
+        ("keys", Value::Array(a)) => Ok(Value::from((0..a.len()).collect::<Vec<_>>())),
+        // Values follow the order of the keys.
+        ("values", Value::Object(m)) => {
+            let mut entries = m.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(k, _)| *k);
+            Ok(Value::Array(
+                entries.into_iter().map(|(_, v)| v.clone()).collect(),
+            ))
+        }
+        ("values", Value::Array(_)) => Ok(data.clone()),
+        ("type", _) => Ok(Value::from(type_name(data))),
+        _ => Err(invalid()),
+    }
+}
+
+/// The name of the type of `value`, as returned by `type`.
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Whether `container` has `key`, which is a string for an object and an index for an array.
+/// Backs both `has(key)` on the container and `in(container)` on the key.
+fn contains(name: &str, container: &Value, key: &Value) -> QueryResult<Value> {
+    match (container, key) {
+        (Value::Object(m), Value::String(k)) => Ok(Value::Bool(m.contains_key(k))),
+        (Value::Array(a), Value::Number(n)) => Ok(Value::Bool(
+            n.as_f64().is_some_and(|i| i >= 0.0 && i < a.len() as f64),
+        )),
+        (Value::Object(_) | Value::Array(_), _) => Err(QueryError::InvalidFunctionInput(
+            name.to_string(),
+            key.clone(),
+        )),
+        _ => Err(QueryError::InvalidFunctionInput(
+            name.to_string(),
+            container.clone(),
+        )),
+    }
+}
+
+/// Evaluates every argument against `data` and returns each combination of their values, as
+/// an argument producing several values calls the function once for each of them.
+fn argument_combinations(
+    compiled: &Query,
+    arguments: &[Expression],
+    data: &Value,
+) -> QueryResult<Vec<Vec<Value>>> {
+    let mut combinations = vec![Vec::with_capacity(arguments.len())];
+    for argument in arguments {
+        let values = evaluate_expression(compiled, argument, data)?;
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |v| {
+                    let mut combination = combination.clone();
+                    combination.push(v.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+    Ok(combinations)
+}
+
+/// `test`, `match`, `capture`, `sub` and `gsub`, which all take a string input, a pattern and
+/// optional flags. `sub` and `gsub` also take a replacement, where `$1` or `$name` refer to
+/// the groups of the match.
+fn regex_function(
+    compiled: &Query,
+    name: &str,
+    arguments: &[Expression],
+    data: &Value,
+) -> QueryResult<Vec<Value>> {
+    let input = match data {
+        Value::String(input) => input,
+        _ => {
+            return Err(QueryError::InvalidFunctionInput(
+                name.to_string(),
+                data.clone(),
+            ))
+        }
+    };
+    let mut results = Vec::new();
+    for combination in argument_combinations(compiled, arguments, data)? {
+        let strings = combination
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .ok_or_else(|| QueryError::InvalidFunctionInput(name.to_string(), v.clone()))
+            })
+            .collect::<QueryResult<Vec<&str>>>()?;
+        let (pattern, replacement, flags) = match (name, &strings[..]) {
+            ("sub" | "gsub", [pattern, replacement, rest @ ..]) => (
+                *pattern,
+                *replacement,
+                rest.first().copied().unwrap_or_default(),
+            ),
+            (_, [pattern, rest @ ..]) => (*pattern, "", rest.first().copied().unwrap_or_default()),
+            _ => unreachable!("regex functions take at least one argument"),
+        };
+        let regex = compiled.regexes().get(pattern, flags)?;
+        let global = name == "gsub" || flags.contains('g');
+        let matches = regex
+            .captures_iter(input)
+            .filter(|c| !(flags.contains('n') && c.get(0).is_some_and(|m| m.as_str().is_empty())))
+            .take(if global { usize::MAX } else { 1 });
+        match name {
+            "test" => results.push(Value::Bool(regex.is_match(input))),
+            "match" => results.extend(matches.map(|c| match_object(&regex, input, &c))),
+            "capture" => results.extend(matches.map(|c| capture_object(&regex, &c))),
+            _ => results.push(Value::String(
+                regex
+                    .replacen(input, if global { 0 } else { 1 }, replacement)
+                    .into_owned(),
+            )),
+        }
+    }
+    Ok(results)
+}
+
+/// Offsets and lengths are counted in characters rather than bytes, as they are in jq.
+fn match_object(regex: &Regex, input: &str, captures: &Captures) -> Value {
+    let mut groups = regex
+        .capture_names()
+        .enumerate()
+        .map(|(i, name)| match captures.get(i) {
+            Some(m) => json!({
+                "offset": input[..m.start()].chars().count(),
+                "length": m.as_str().chars().count(),
+                "string": m.as_str(),
+                "name": name,
+            }),
+            None => json!({"offset": -1, "length": 0, "string": null, "name": name}),
+        });
+    let mut whole = groups.next().unwrap_or_default();
+    if let Value::Object(m) = &mut whole {
+        m.remove("name");
+        m.insert("captures".to_string(), Value::Array(groups.collect()));
+    }
+    whole
+}
+
+/// The named groups of a match, where groups that did not take part in the match are `null`.
+fn capture_object(regex: &Regex, captures: &Captures) -> Value {
+    regex
+        .capture_names()
+        .enumerate()
+        .filter_map(|(i, name)| {
+            let value = captures
+                .get(i)
+                .map_or(Value::Null, |m| Value::String(m.as_str().to_string()));
+            name.map(|name| (name.to_string(), value))
+        })
+        .collect::<Map<String, Value>>()
+        .into()
+}
+
+/// `lhs =~ rhs`, which only holds for a string matched by the pattern. Other values never match.
+pub(crate) fn regex_matches(compiled: &Query, lhs: &Value, rhs: &Value) -> QueryResult<bool> {
+    match (lhs, rhs) {
+        (Value::String(input), Value::String(pattern)) => {
+            Ok(compiled.regexes().get(pattern, "")?.is_match(input))
+        }
+        (_, Value::String(_)) => Ok(false),
+        _ => Err(QueryError::InvalidFunctionInput(
+            Comparator::RegexMatch.to_string(),
+            rhs.clone(),
+        )),
+    }
+}
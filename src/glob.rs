@@ -0,0 +1,184 @@
+use crate::query::{walk_operations, Visitor};
+use crate::{QueryError, QueryResult};
+use data_query_lexical::{GenericObjectIndex, LexOperator};
+use std::borrow::Cow;
+use std::collections::{HashMap, LinkedList};
+
+/// A single element of a glob pattern.
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(char),
+    /// `?`
+    One,
+    /// `*`
+    Any,
+    /// `[a-z_]`, or `[!a-z_]` and `[^a-z_]` when negated.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(l) => *l == c,
+            Token::One | Token::Any => true,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(from, to)| (*from..=*to).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// Splits `pattern` into tokens, or `None` when a character class is missing its closing `]`.
+fn tokens(pattern: &str) -> Option<Vec<Token>> {
+    let mut chars = pattern.chars().peekable();
+    let mut tokens = vec![];
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '\\' => Token::Literal(chars.next().unwrap_or(c)),
+            '?' => Token::One,
+            '*' => Token::Any,
+            '[' => {
+                let negated = chars.next_if(|c| *c == '!' || *c == '^').is_some();
+                let mut ranges = vec![];
+                loop {
+                    let from = match chars.next()? {
+                        ']' => break,
+                        '\\' => chars.next()?,
+                        c => c,
+                    };
+                    let to = match chars.peek() {
+                        Some('-') => {
+                            chars.next();
+                            match chars.next()? {
+                                ']' => {
+                                    // A trailing `-` is literal, as in `[a-]`.
+                                    ranges.push((from, from));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                '\\' => chars.next()?,
+                                to => to,
+                            }
+                        }
+                        _ => from,
+                    };
+                    ranges.push((from, to));
+                }
+                Token::Class { negated, ranges }
+            }
+            _ => Token::Literal(c),
+        });
+    }
+    Some(tokens)
+}
+
+/// A glob pattern split into tokens, so matching a key does not read the pattern again.
+#[derive(Debug, Clone)]
+pub(crate) struct Glob(Vec<Token>);
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> QueryResult<Self> {
+        tokens(pattern)
+            .map(Self)
+            .ok_or_else(|| QueryError::InvalidGlobPattern(pattern.to_string()))
+    }
+
+    /// Whether `key` matches the pattern as a whole. `*` matches any sequence of characters,
+    /// dots and slashes included, since keys are flat strings.
+    pub(crate) fn matches(&self, key: &str) -> bool {
+        let tokens = &self.0;
+        let key: Vec<char> = key.chars().collect();
+        let (mut t, mut k) = (0, 0);
+        // The last `*` seen and the position in the key it currently stretches to.
+        let mut star: Option<(usize, usize)> = None;
+        while k < key.len() {
+            match tokens.get(t) {
+                Some(Token::Any) => {
+                    star = Some((t, k));
+                    t += 1;
+                }
+                Some(token) if token.matches(key[k]) => {
+                    t += 1;
+                    k += 1;
+                }
+                _ => match star {
+                    Some((star_t, star_k)) => {
+                        t = star_t + 1;
+                        k = star_k + 1;
+                        star = Some((star_t, star_k + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        tokens[t..].iter().all(|t| matches!(t, Token::Any))
+    }
+}
+
+/// Glob patterns compiled together with the query, keyed by pattern, so a pattern is not read
+/// again for every map it is matched against.
+#[derive(Debug, Default)]
+pub(crate) struct GlobCache {
+    globs: HashMap<String, Glob>,
+}
+
+impl GlobCache {
+    /// Compiles every glob pattern used by `operations`. Invalid patterns are left out and
+    /// reported when the query is evaluated.
+    pub(crate) fn new(operations: &LinkedList<LexOperator>) -> Self {
+        let mut cache = Self::default();
+        walk_operations(operations, &mut cache);
+        cache
+    }
+
+    pub(crate) fn get(&self, pattern: &str) -> QueryResult<Cow<'_, Glob>> {
+        match self.globs.get(pattern) {
+            Some(glob) => Ok(Cow::Borrowed(glob)),
+            None => Glob::new(pattern).map(Cow::Owned),
+        }
+    }
+}
+
+impl Visitor for GlobCache {
+    fn operator(&mut self, operator: &LexOperator) {
+        if let LexOperator::Generic(GenericObjectIndex::Glob(pattern)) = operator {
+            if let Ok(glob) = Glob::new(pattern) {
+                self.globs.insert(pattern.clone(), glob);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Glob;
+
+    #[test]
+    fn test_glob_matches() {
+        let glob_matches = |pattern: &str, key: &str| Glob::new(pattern).unwrap().matches(key);
+        assert!(glob_matches(
+            "app.kubernetes.io/*",
+            "app.kubernetes.io/name"
+        ));
+        assert!(!glob_matches("app.kubernetes.io/*", "helm.sh/chart"));
+        assert!(glob_matches("*/status", "deployment.kubernetes.io/status"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "aXbYbc"));
+        assert!(!glob_matches("a*b*c", "aXbYbcd"));
+        assert!(glob_matches("v?", "v1"));
+        assert!(!glob_matches("v?", "v"));
+        assert!(glob_matches("v[0-9]", "v2"));
+        assert!(!glob_matches("v[!0-9]", "v2"));
+        assert!(glob_matches("v[^0-9]", "vx"));
+        assert!(glob_matches("[a-]", "-"));
+        assert!(glob_matches(r"a\*", "a*"));
+        assert!(!glob_matches(r"a\*", "ab"));
+        assert!(glob_matches("é*", "été"));
+        assert!(Glob::new("v[0-9").is_err());
+        assert!(Glob::new("v[0-").is_err());
+        assert!(Glob::new(r"v[\").is_err());
+    }
+}
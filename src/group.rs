@@ -0,0 +1,100 @@
+use crate::expression::evaluate_expression;
+use crate::function::array_input;
+use crate::ordering::{sort_keys, total_cmp};
+use crate::{Query, QueryError, QueryResult};
+use data_query_lexical::Expression;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// `group_by(key)`, the elements of the input array split into arrays of elements with an equal
+/// key, in the order of the keys. The key of an element is the array of every value `key` produces
+/// for it, and the elements of a group keep their order.
+pub(crate) fn group_by(compiled: &Query, key: &Expression, data: &Value) -> QueryResult<Value> {
+    let elements = array_input("group_by", data)?;
+    let keys = sort_keys(compiled, key, elements)?;
+    let mut order = (0..elements.len()).collect::<Vec<_>>();
+    order.sort_by(|l, r| total_cmp(&keys[*l], &keys[*r]));
+    let mut groups: Vec<(usize, Vec<Value>)> = Vec::new();
+    for i in order {
+        match groups.last_mut() {
+            Some((first, group)) if total_cmp(&keys[*first], &keys[i]) == Ordering::Equal => {
+                group.push(elements[i].clone())
+            }
+            _ => groups.push((i, vec![elements[i].clone()])),
+        }
+    }
+    Ok(Value::Array(
+        groups
+            .into_iter()
+            .map(|(_, group)| Value::Array(group))
+            .collect(),
+    ))
+}
+
+/// `count_by(key)`, an object holding how many elements of the input array produce each key. An
+/// element counts once for every value `key` produces, so it may count under several keys or none.
+pub(crate) fn count_by(compiled: &Query, key: &Expression, data: &Value) -> QueryResult<Value> {
+    let mut counts = Map::new();
+    for element in array_input("count_by", data)? {
+        for k in evaluate_expression(compiled, key, element)? {
+            let count = counts
+                .entry(object_key("count_by", k)?)
+                .or_insert(Value::from(0u64));
+            *count = Value::from(count.as_u64().unwrap_or_default() + 1);
+        }
+    }
+    Ok(Value::Object(counts))
+}
+
+/// `to_object(key; value)`, an object built from the input array with an entry for every key and
+/// value an element produces, or the element itself without `value`. Later entries replace earlier
+/// ones of the same key.
+pub(crate) fn to_object(
+    compiled: &Query,
+    key: &Expression,
+    value: Option<&Expression>,
+    data: &Value,
+) -> QueryResult<Value> {
+    let mut object = Map::new();
+    for element in array_input("to_object", data)? {
+        let values = match value {
+            Some(value) => evaluate_expression(compiled, value, element)?,
+            None => vec![element.clone()],
+        };
+        for k in evaluate_expression(compiled, key, element)? {
+            let k = object_key("to_object", k)?;
+            for v in &values {
+                object.insert(k.clone(), v.clone());
+            }
+        }
+    }
+    Ok(Value::Object(object))
+}
+
+/// The key of an object entry. Strings are taken as they are, while `null`, booleans and numbers
+/// are written as JSON.
+fn object_key(name: &str, key: Value) -> QueryResult<String> {
+    match key {
+        Value::String(s) => Ok(s),
+        Value::Null | Value::Bool(_) | Value::Number(_) => Ok(key.to_string()),
+        _ => Err(QueryError::InvalidFunctionInput(name.to_string(), key)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::group::object_key;
+    use serde_json::json;
+
+    #[test]
+    fn test_object_key() {
+        assert_eq!(object_key("to_object", json!("a.b")).unwrap(), "a.b");
+        assert_eq!(object_key("to_object", json!("")).unwrap(), "");
+        assert_eq!(object_key("to_object", json!(null)).unwrap(), "null");
+        assert_eq!(object_key("to_object", json!(false)).unwrap(), "false");
+        assert_eq!(object_key("to_object", json!(8080)).unwrap(), "8080");
+        assert_eq!(object_key("to_object", json!(0.5)).unwrap(), "0.5");
+        assert!(object_key("to_object", json!(["a"])).is_err());
+        assert!(object_key("to_object", json!({"a": 1})).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 extern crate data_query_lexical;
-#[macro_use]
+#[cfg_attr(test, macro_use)]
 extern crate data_query_proc;
 extern crate jq_rs;
 extern crate railsgun;
@@ -8,107 +8,169 @@ extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
 
+mod aggregate;
 mod error;
+mod expression;
+mod function;
+mod glob;
+mod group;
+mod options;
+mod ordering;
+mod path;
+mod query;
 
 pub use crate::error::QueryError;
-use data_query_lexical::{GenericObjectIndex, LexOperator, LexicalOperations, Slicer};
+use crate::expression::{evaluate_expression, filter_matches, is_selection, select_expression};
+use crate::function::type_name;
+pub use crate::options::{MissingPath, QueryOptions};
+use crate::ordering::total_cmp;
+pub use crate::path::{Path, PathSegment};
+pub use crate::query::Query;
+use data_query_lexical::{GenericObjectIndex, LexOperator, Slicer};
 
+#[cfg(not(feature = "jq"))]
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use serde_json::{Map, Value};
+use serde_json::{Map, Number, Value};
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::linked_list::Iter;
+use std::ops::ControlFlow;
 
 /// Alias for a `Result` with the error type `serde_json::Error`.
 pub type QueryResult<T> = std::result::Result<T, QueryError>;
 
-struct ComType {
-    usize: Option<usize>,
-    string: Option<String>,
+/// A key as generic indices compare it. Keys that read as numbers, such as `-1`, `1.5` or `1e3`,
+/// are numbers, so in the total ordering of values they compare numerically and sort before every
+/// other key. JSON numbers keep large integers exact, while `f64` also reads keys such as `007`.
+fn key_value(key: &str) -> Value {
+    let number = key.parse::<Number>().ok().or_else(|| {
+        key.parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .and_then(Number::from_f64)
+    });
+    match number {
+        Some(number) => Value::Number(number),
+        None => Value::from(key),
+    }
 }
 
-impl PartialOrd<Self> for ComType {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        use Ordering::*;
-        if let (Some(s), Some(o)) = (self.usize, other.usize) {
-            if s > o {
-                return Some(Greater);
-            } else if s < o {
-                return Some(Less);
-            } else if s == o {
-                return Some(Equal);
-            }
-        }
-        None
-    }
+/// Compares two keys through the total ordering of values, see [`key_value`].
+fn compare_keys(lhs: &str, rhs: &str) -> Ordering {
+    total_cmp(&key_value(lhs), &key_value(rhs))
 }
 
-// impl Ord for ComType {
-//     fn cmp(&self, other: &Self) -> Ordering {
-//         todo!()
-//     }
-// }
+/// Runs `query` against any serializable data, returning every match in a `Value::Array`.
+///
+/// The query may be a query string, the output of `compile`/`precompile_lex!` or a [`Query`].
+#[cfg(not(feature = "jq"))]
+pub fn query<S: Serialize, Q: TryInto<Query>>(s: S, query: Q) -> QueryResult<Value>
+where
+    QueryError: From<Q::Error>,
+{
+    query.try_into().map_err(QueryError::from)?.execute(s)
+}
 
-impl Eq for ComType {}
+/// Runs `query` against `s`, expecting exactly one result. See [`Query::execute_one`].
+#[cfg(not(feature = "jq"))]
+pub fn query_one<S: Serialize, Q: TryInto<Query>>(s: S, query: Q) -> QueryResult<Value>
+where
+    QueryError: From<Q::Error>,
+{
+    query.try_into().map_err(QueryError::from)?.execute_one(s)
+}
 
-impl PartialEq for ComType {
-    fn eq(&self, other: &Self) -> bool {
-        if let (Some(s), Some(o)) = (self.usize, other.usize) {
-            s == o
-        } else {
-            false
-        }
-    }
+/// Runs `query` against `s` until its first result. See [`Query::execute_first`].
+#[cfg(not(feature = "jq"))]
+pub fn query_first<S: Serialize, Q: TryInto<Query>>(s: S, query: Q) -> QueryResult<Value>
+where
+    QueryError: From<Q::Error>,
+{
+    query.try_into().map_err(QueryError::from)?.execute_first(s)
 }
 
-impl From<usize> for ComType {
-    fn from(u: usize) -> Self {
-        Self {
-            usize: Some(u),
-            string: Some(format!("{}", u)),
-        }
-    }
+/// Runs `query` against `s`, expecting at most one result. See [`Query::execute_opt`].
+#[cfg(not(feature = "jq"))]
+pub fn query_opt<S: Serialize, Q: TryInto<Query>>(s: S, query: Q) -> QueryResult<Option<Value>>
+where
+    QueryError: From<Q::Error>,
+{
+    query.try_into().map_err(QueryError::from)?.execute_opt(s)
 }
-impl From<&usize> for ComType {
-    fn from(u: &usize) -> Self {
-        Self {
-            usize: Some(*u),
-            string: Some(format!("{}", u)),
-        }
-    }
+
+/// Runs `query` against `s`, expecting exactly one result, and deserializes it into `T`. See
+/// [`Query::execute_as`].
+///
+/// ```
+/// use data_query::query_as;
+///
+/// let data = serde_json::json!({"ports": [{"containerPort": 8080}, {"containerPort": 8443}]});
+/// let port: u16 = query_as(&data, ".ports[0].containerPort").unwrap();
+/// assert_eq!(port, 8080);
+/// ```
+#[cfg(not(feature = "jq"))]
+pub fn query_as<T: DeserializeOwned>(
+    s: impl Serialize,
+    query: impl TryInto<Query, Error = impl Into<QueryError>>,
+) -> QueryResult<T> {
+    query.try_into().map_err(Into::into)?.execute_as(s)
 }
 
-impl From<String> for ComType {
-    fn from(s: String) -> Self {
-        Self {
-            usize: s.parse::<usize>().ok(),
-            string: Some(s),
-        }
-    }
+/// Runs `query` against `s` and deserializes every result into `T`. See
+/// [`Query::execute_vec_as`].
+#[cfg(not(feature = "jq"))]
+pub fn query_vec_as<T: DeserializeOwned>(
+    s: impl Serialize,
+    query: impl TryInto<Query, Error = impl Into<QueryError>>,
+) -> QueryResult<Vec<T>> {
+    query.try_into().map_err(Into::into)?.execute_vec_as(s)
 }
 
-impl From<&str> for ComType {
-    fn from(s: &str) -> Self {
-        Self::from(s.to_string())
-    }
+/// Runs `query` against `s`, returning every match together with its path in the data. See
+/// [`Query::execute_with_paths`].
+#[cfg(not(feature = "jq"))]
+pub fn query_with_paths<S: Serialize, Q: TryInto<Query>>(
+    s: S,
+    query: Q,
+) -> QueryResult<Vec<(Path, Value)>>
+where
+    QueryError: From<Q::Error>,
+{
+    query
+        .try_into()
+        .map_err(QueryError::from)?
+        .execute_with_paths(s)
 }
 
-impl From<&mut usize> for ComType {
-    fn from(u: &mut usize) -> Self {
-        Self::from(u.clone())
-    }
+/// Runs `query` against an already serialized value, returning references to the matches. See
+/// [`Query::evaluate_refs`].
+#[cfg(not(feature = "jq"))]
+pub fn query_refs<Q: TryInto<Query>>(data: &Value, query: Q) -> QueryResult<Vec<&Value>>
+where
+    QueryError: From<Q::Error>,
+{
+    query
+        .try_into()
+        .map_err(QueryError::from)?
+        .evaluate_refs(data)
 }
 
+/// Runs `query` against an already serialized value, handing every match to `f` as soon as it is
+/// found. See [`Query::try_for_each`].
 #[cfg(not(feature = "jq"))]
-pub fn query<S: Serialize, Q: TryInto<LexicalOperations>>(s: S, query: Q) -> QueryResult<Value> {
-    {
-        let mut lexes = query
-            .try_into()
-            // TODO: This error handling needs to be fixed!
-            .map_err(|_e| QueryError::from(format!("Gulp")))?;
-        let data = serde_json::to_value(s).map_err(QueryError::from)?;
-        let mut results = Vec::new();
-        query_processor(&data, &mut lexes, &mut results, 0)?;
-        Ok(Value::Array(results))
-    }
+pub fn query_try_for_each<B, Q: TryInto<Query>>(
+    data: &Value,
+    query: Q,
+    f: impl FnMut(&Value) -> ControlFlow<B>,
+) -> QueryResult<ControlFlow<B>>
+where
+    QueryError: From<Q::Error>,
+{
+    query
+        .try_into()
+        .map_err(QueryError::from)?
+        .try_for_each(data, f)
 }
 
 #[cfg(feature = "jq")]
@@ -119,162 +181,653 @@ pub fn query<S: Serialize>(s: S, query: &str) -> QueryResult<Value> {
     serde_json::from_str(json_value.trim()).map_err(QueryError::from)
 }
 
-fn query_processor(
-    data: &Value,
-    query: &mut LexicalOperations,
-    results: &mut Vec<Value>,
-    mut depth: usize,
-) -> QueryResult<()> {
-    depth += 1;
-    if query.is_empty() {
-        results.push(data.clone());
-        return Ok(());
-    } else {
-        let key_query = query
-            .pop_front()
-            .ok_or(QueryError::UncontrolledError("Empty".to_string()))?;
-        match data {
-            Value::Array(v) => match key_query.clone() {
-                LexOperator::Identifier(ident) => {
-                    return if let Ok(i) = ident.parse::<usize>() {
-                        query_processor(&v[i], query, results, depth)
-                    } else {
-                        Err(QueryError::CannotUseIdentifierAsArrayKeyIndex(ident))
-                    };
-                }
-                LexOperator::Pipe(_p) => {
-                    todo!();
+/// The value walked in place of a key or index that is missing, when they read as `null`.
+static NULL: Value = Value::Null;
+
+/// A callback handed every result as soon as it is found, which stops the walk by breaking.
+pub(crate) type Sink<'a> = &'a mut dyn FnMut(&Value) -> ControlFlow<()>;
+
+/// The values produced by a query, collected until an optional limit is reached. Values found in
+/// the document are borrowed from it, values computed by an expression are owned.
+pub(crate) struct Results<'a> {
+    values: Vec<Cow<'a, Value>>,
+    limit: Option<usize>,
+    /// When paths are tracked, the path to the value being walked and the path of every result,
+    /// which computed results do not have.
+    paths: Option<(Vec<PathSegment>, Vec<Option<Path>>)>,
+    /// Whether every result has to be found in the document, which rules out computed values.
+    borrowed: bool,
+    /// When set, results are handed to the sink instead of being collected.
+    sink: Option<Sink<'a>>,
+    /// Whether the sink has asked for the walk to stop.
+    stopped: bool,
+}
+
+impl<'a> Results<'a> {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            values: Vec::new(),
+            limit,
+            paths: None,
+            borrowed: false,
+            sink: None,
+            stopped: false,
+        }
+    }
+
+    /// Hands every result to `sink` as soon as it is found rather than collecting it.
+    pub(crate) fn with_sink(mut self, sink: Sink<'a>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Only accepts results found in the document, so they can be returned as references.
+    pub(crate) fn borrowed(mut self) -> Self {
+        self.borrowed = true;
+        self
+    }
+
+    /// Also records the path of every result found in the document, while computed results are
+    /// still accepted without one.
+    pub(crate) fn with_result_paths(mut self) -> Self {
+        self.paths = Some(Default::default());
+        self
+    }
+
+    /// Also records the path of every result, which only results found in the document have.
+    pub(crate) fn with_paths(self) -> Self {
+        self.with_result_paths().borrowed()
+    }
+
+    pub(crate) fn tracks_paths(&self) -> bool {
+        self.paths.is_some()
+    }
+
+    fn push(&mut self, value: &'a Value) {
+        if let Some(sink) = &mut self.sink {
+            self.stopped = sink(value).is_break();
+            return;
+        }
+        if let Some((current, paths)) = &mut self.paths {
+            paths.push(Some(current.clone().into()));
+        }
+        self.values.push(Cow::Borrowed(value));
+    }
+
+    /// Walks `f` on a value computed by the query rather than found in the document, keeping
+    /// owned copies of the results.
+    fn detached<'s, F>(&'s mut self, f: F) -> QueryResult<()>
+    where
+        F: FnOnce(&mut Results<'s>) -> QueryResult<()>,
+    {
+        let limit = self.limit.map(|limit| limit - self.values.len());
+        let mut detached = Results::new(limit);
+        detached.sink = self.sink.as_mut().map(|sink| &mut **sink as Sink);
+        let result = f(&mut detached);
+        let Results {
+            values, stopped, ..
+        } = detached;
+        self.stopped = stopped;
+        if let Some((_, paths)) = &mut self.paths {
+            paths.resize(paths.len() + values.len(), None);
+        }
+        self.values.extend(
+            values
+                .into_iter()
+                .map(|value| Cow::Owned(value.into_owned())),
+        );
+        result
+    }
+
+    /// Walks `f` on a child of the current value. The segment leading to it is only built when
+    /// paths are tracked.
+    fn descend<F>(&mut self, segment: impl FnOnce() -> PathSegment, f: F) -> QueryResult<()>
+    where
+        F: FnOnce(&mut Self) -> QueryResult<()>,
+    {
+        match &mut self.paths {
+            Some((current, _)) => {
+                current.push(segment());
+                let result = f(self);
+                if let Some((current, _)) = &mut self.paths {
+                    current.pop();
                 }
-                LexOperator::Generic(mut g) => {
-                    return query_slice_w_generic_object_index(&v, &mut g, query, results, depth);
+                result
+            }
+            None => f(self),
+        }
+    }
+
+    /// Walks `f` on a value below the current one, `path` leading from one to the other.
+    fn descend_path<F>(&mut self, path: &[PathSegment], f: F) -> QueryResult<()>
+    where
+        F: FnOnce(&mut Self) -> QueryResult<()>,
+    {
+        match &mut self.paths {
+            Some((current, _)) => {
+                let len = current.len();
+                current.extend_from_slice(path);
+                let result = f(self);
+                if let Some((current, _)) = &mut self.paths {
+                    current.truncate(len);
                 }
+                result
+            }
+            None => f(self),
+        }
+    }
+
+    /// Whether the limit has been reached or the sink has stopped the walk, after which the rest
+    /// of the document is not walked.
+    fn is_full(&self) -> bool {
+        self.stopped || self.limit.is_some_and(|limit| self.values.len() >= limit)
+    }
+
+    pub(crate) fn into_values(self) -> Vec<Value> {
+        self.values.into_iter().map(Cow::into_owned).collect()
+    }
+
+    /// The results of a walk that only accepts results found in the document.
+    pub(crate) fn into_refs(self) -> Vec<&'a Value> {
+        self.values
+            .into_iter()
+            .map(|value| match value {
+                Cow::Borrowed(value) => value,
+                Cow::Owned(_) => unreachable!("borrowed results never hold computed values"),
+            })
+            .collect()
+    }
+
+    /// Pairs every result with its path, which is empty unless paths are tracked.
+    pub(crate) fn into_paths(mut self) -> Vec<(Path, &'a Value)> {
+        let paths = self.paths.take().map(|(_, paths)| paths);
+        let values = self.into_refs();
+        match paths {
+            Some(paths) => paths
+                .into_iter()
+                .map(|path| path.expect("borrowed results are always found in the document"))
+                .zip(values)
+                .collect(),
+            None => values.into_iter().map(|v| (Path::default(), v)).collect(),
+        }
+    }
+
+    /// Pairs every result with its path, which computed results and untracked walks do not have.
+    pub(crate) fn into_values_with_paths(mut self) -> Vec<(Option<Path>, Value)> {
+        let paths = self.paths.take().map(|(_, paths)| paths);
+        let values = self.into_values();
+        match paths {
+            Some(paths) => paths.into_iter().zip(values).collect(),
+            None => values.into_iter().map(|v| (None, v)).collect(),
+        }
+    }
+}
+
+/// Walks `data` along the remaining `query` operations, pushing every match onto `results`.
+///
+/// The operations are only ever borrowed: whenever a generic index fans out over several
+/// elements, each branch gets its own copy of the iterator and walks the remaining path
+/// independently of its siblings.
+fn query_processor<'a>(
+    compiled: &Query,
+    data: &'a Value,
+    mut query: Iter<LexOperator>,
+    results: &mut Results<'a>,
+    depth: usize,
+) -> QueryResult<()> {
+    if results.is_full() {
+        return Ok(());
+    }
+    match query.next() {
+        None => {
+            results.push(data);
+            Ok(())
+        }
+        Some(key_query) => {
+            query_operator(compiled, data, key_query, false, query, results, depth + 1)
+        }
+    }
+}
+
+/// Applies a single operator to `data` and walks the remaining `query` from every match. An
+/// `optional` operator skips the branch when its key or index is missing, whatever the options.
+fn query_operator<'a>(
+    compiled: &Query,
+    data: &'a Value,
+    key_query: &LexOperator,
+    optional: bool,
+    query: Iter<LexOperator>,
+    results: &mut Results<'a>,
+    depth: usize,
+) -> QueryResult<()> {
+    match (data, key_query) {
+        // A pipe feeds the current value, whatever its type, to the right hand side. Because the
+        // left hand side has already fanned out by the time a branch reaches the pipe, every
+        // result of the left side is evaluated independently and the outputs are concatenated.
+        (_, LexOperator::Pipe(p)) => query_processor(compiled, data, p.iter(), results, depth),
+        (Value::Array(v), LexOperator::Identifier(ident)) => match ident.parse::<usize>() {
+            Ok(i) => match v.get(i) {
+                Some(value) => results.descend(
+                    || PathSegment::Index(i),
+                    |results| query_processor(compiled, value, query, results, depth),
+                ),
+                None => query_missing(
+                    compiled,
+                    QueryError::IndexOutOfRange(i),
+                    PathSegment::Index(i),
+                    optional,
+                    query,
+                    results,
+                    depth,
+                ),
             },
-            Value::Object(m) => match key_query {
-                LexOperator::Identifier(ident) => {
-                    return if m.contains_key(&ident) {
-                        if let Some(value) = m.get(&*ident) {
-                            query_processor(value, query, results, depth)
-                        } else {
-                            Ok(())
-                        }
-                    } else {
-                        Err(QueryError::CannotUseIdentifierAsArrayKeyIndex(ident))
-                    };
-                }
-                LexOperator::Pipe(_p) => {
-                    todo!();
+            Err(_) => query_missing(
+                compiled,
+                QueryError::CannotUseIdentifierAsArrayKeyIndex(ident.clone()),
+                PathSegment::Key(ident.clone()),
+                optional,
+                query,
+                results,
+                depth,
+            ),
+        },
+        (Value::Array(v), LexOperator::Generic(g)) => {
+            query_slice_w_generic_object_index(compiled, v, g, query, results, depth)
+        }
+        (Value::Object(m), LexOperator::Identifier(ident)) => match m.get(ident) {
+            Some(value) => results.descend(
+                || PathSegment::Key(ident.clone()),
+                |results| query_processor(compiled, value, query, results, depth),
+            ),
+            None => query_missing(
+                compiled,
+                QueryError::KeyNotFound(ident.clone()),
+                PathSegment::Key(ident.clone()),
+                optional,
+                query,
+                results,
+                depth,
+            ),
+        },
+        (Value::Object(m), LexOperator::Generic(g)) => {
+            query_map_w_generic_object_index(compiled, m, g, query, results, depth)
+        }
+        (Value::Null, LexOperator::Identifier(ident))
+            if !optional && compiled.options().missing() == MissingPath::Null =>
+        {
+            results.descend(
+                || PathSegment::Key(ident.clone()),
+                |results| query_processor(compiled, data, query, results, depth),
+            )
+        }
+        (_, LexOperator::RecursiveDescent(operator)) => {
+            query_recursive_descent(compiled, data, operator, query, results, depth)
+        }
+        (_, LexOperator::Optional(operator)) => {
+            query_operator(compiled, data, operator, true, query, results, depth)
+        }
+        // Values computed by an expression have no place in the document, so only expressions
+        // made of paths can be walked when results are borrowed or their paths tracked. Those are
+        // still selected when paths are tracked, so the results they find keep their path.
+        (_, LexOperator::Expression(e))
+            if results.borrowed || (results.tracks_paths() && is_selection(e)) =>
+        {
+            for (path, value) in select_expression(compiled, e, data, results.tracks_paths())? {
+                if results.is_full() {
+                    break;
                 }
-                LexOperator::Generic(mut g) => {
-                    return query_map_w_generic_object_index(m, &mut g, query, results, depth);
+                results.descend_path(path.segments(), |results| {
+                    query_processor(compiled, value, query.clone(), results, depth)
+                })?;
+            }
+            Ok(())
+        }
+        (_, LexOperator::Expression(e)) => {
+            for value in evaluate_expression(compiled, e, data)? {
+                if results.is_full() {
+                    break;
                 }
-            },
-            _ => {
-                return Ok(());
+                results.detached(|results| {
+                    query_processor(compiled, &value, query.clone(), results, depth)
+                })?;
             }
+            Ok(())
+        }
+        // Keys and indices of a scalar are as missing as those of an object or array without
+        // them, but in strict mode the error names the type that cannot be indexed.
+        (_, LexOperator::Identifier(_) | LexOperator::Generic(_))
+            if !optional && compiled.options().missing() == MissingPath::Error =>
+        {
+            Err(QueryError::CannotIndex(
+                type_name(data).to_string(),
+                key_query.to_string(),
+            ))
         }
+        _ => Ok(()),
+    }
+}
+
+/// Handles a key or index that is not there, following the `MissingPath` option unless the
+/// segment is optional.
+fn query_missing(
+    compiled: &Query,
+    error: QueryError,
+    segment: PathSegment,
+    optional: bool,
+    query: Iter<LexOperator>,
+    results: &mut Results,
+    depth: usize,
+) -> QueryResult<()> {
+    if optional {
+        return Ok(());
+    }
+    match compiled.options().missing() {
+        MissingPath::Error => Err(error),
+        MissingPath::Skip => Ok(()),
+        MissingPath::Null => results.descend(
+            || segment,
+            |results| query_processor(compiled, &NULL, query, results, depth),
+        ),
+    }
+}
+
+/// Applies `operator` to `data` and to every node below it, in document order, and walks the
+/// remaining `query` from each match. Nodes the operator does not apply to, such as objects
+/// without the key, are skipped rather than treated as errors.
+fn query_recursive_descent<'a>(
+    compiled: &Query,
+    data: &'a Value,
+    operator: &LexOperator,
+    query: Iter<LexOperator>,
+    results: &mut Results<'a>,
+    depth: usize,
+) -> QueryResult<()> {
+    if results.is_full() {
+        return Ok(());
+    }
+    match (data, operator) {
+        (Value::Object(m), LexOperator::Identifier(ident)) => {
+            if let Some(value) = m.get(ident) {
+                results.descend(
+                    || PathSegment::Key(ident.clone()),
+                    |results| query_processor(compiled, value, query.clone(), results, depth),
+                )?;
+            }
+        }
+        (Value::Array(v), LexOperator::Generic(g)) => {
+            query_slice_w_generic_object_index(compiled, v, g, query.clone(), results, depth)?;
+        }
+        (Value::Object(m), LexOperator::Generic(g)) => {
+            query_map_w_generic_object_index(compiled, m, g, query.clone(), results, depth)?;
+        }
+        _ => {}
+    }
+    match data {
+        Value::Array(v) => {
+            for (i, child) in v.iter().enumerate() {
+                results.descend(
+                    || PathSegment::Index(i),
+                    |results| {
+                        query_recursive_descent(
+                            compiled,
+                            child,
+                            operator,
+                            query.clone(),
+                            results,
+                            depth + 1,
+                        )
+                    },
+                )?;
+            }
+        }
+        Value::Object(m) => {
+            for (k, child) in m {
+                results.descend(
+                    || PathSegment::Key(k.clone()),
+                    |results| {
+                        query_recursive_descent(
+                            compiled,
+                            child,
+                            operator,
+                            query.clone(),
+                            results,
+                            depth + 1,
+                        )
+                    },
+                )?;
+            }
+        }
+        _ => {}
     }
     Ok(())
 }
 
-fn query_slice_w_generic_object_index(
-    data: &Vec<Value>,
-    index_match: &mut GenericObjectIndex,
-    query: &mut LexicalOperations,
-    results: &mut Vec<Value>,
+fn query_slice_w_generic_object_index<'a>(
+    compiled: &Query,
+    data: &'a [Value],
+    index_match: &GenericObjectIndex,
+    query: Iter<LexOperator>,
+    results: &mut Results<'a>,
     depth: usize,
 ) -> QueryResult<()> {
-    for (k, v) in data.iter().enumerate() {
-        if match_slice_to_key(&format!("{}", k), index_match) {
-            query_processor(v, query, results, depth)?
+    if let GenericObjectIndex::Filter(filter) = index_match {
+        for (k, v) in data.iter().enumerate() {
+            if results.is_full() {
+                break;
+            }
+            if filter_matches(compiled, filter, v)? {
+                results.descend(
+                    || PathSegment::Index(k),
+                    |results| query_processor(compiled, v, query.clone(), results, depth),
+                )?
+            }
+        }
+        return Ok(());
+    }
+    // A selector made up only of negative step slices walks the array backwards.
+    let reverse = matches!(index_match, GenericObjectIndex::Slice(slice)
+        if !slice.is_empty()
+            && slice.iter().all(|s| matches!(s, Slicer::Step(_, _, Some(step)) if *step < 0)));
+    let elements: Box<dyn Iterator<Item = (usize, &Value)>> = if reverse {
+        Box::new(data.iter().enumerate().rev())
+    } else {
+        Box::new(data.iter().enumerate())
+    };
+    for (k, v) in elements {
+        if results.is_full() {
+            break;
+        }
+        if match_slice_to_key(&format!("{}", k), index_match, Some(data.len())) {
+            results.descend(
+                || PathSegment::Index(k),
+                |results| query_processor(compiled, v, query.clone(), results, depth),
+            )?
         }
     }
     Ok(())
 }
 
-fn query_map_w_generic_object_index(
-    data: &Map<String, Value>,
-    index_match: &mut GenericObjectIndex,
-    query: &mut LexicalOperations,
-    results: &mut Vec<Value>,
+fn query_map_w_generic_object_index<'a>(
+    compiled: &Query,
+    data: &'a Map<String, Value>,
+    index_match: &GenericObjectIndex,
+    query: Iter<LexOperator>,
+    results: &mut Results<'a>,
     depth: usize,
 ) -> QueryResult<()> {
+    if let GenericObjectIndex::Filter(filter) = index_match {
+        for (k, v) in data.iter() {
+            if results.is_full() {
+                break;
+            }
+            if filter_matches(compiled, filter, v)? {
+                results.descend(
+                    || PathSegment::Key(k.clone()),
+                    |results| query_processor(compiled, v, query.clone(), results, depth),
+                )?
+            }
+        }
+        return Ok(());
+    }
+    let glob = match index_match {
+        GenericObjectIndex::Glob(pattern) => Some(compiled.globs().get(pattern)?),
+        _ => None,
+    };
     for (k, v) in data.iter() {
-        if match_slice_to_key(&format!("{}", k), index_match) {
-            query_processor(v, query, results, depth)?
+        if results.is_full() {
+            break;
+        }
+        let matches = match &glob {
+            Some(glob) => glob.matches(k),
+            None => match_slice_to_key(k, index_match, None),
+        };
+        if matches {
+            results.descend(
+                || PathSegment::Key(k.clone()),
+                |results| query_processor(compiled, v, query.clone(), results, depth),
+            )?
         }
     }
     Ok(())
 }
 
-fn key_match_map(key: &String, query: &LexOperator) -> bool {
-    match query {
-        LexOperator::Identifier(ident) => {
-            if key == ident {
-                true
-            } else {
-                false
-            }
+/// Resolves a bound that may count from the end, where `-1` is the last element of a container
+/// with `len` elements. Maps have no end to count from, so only arrays pass a length.
+fn resolve_index(index: isize, len: Option<usize>) -> Option<usize> {
+    if index >= 0 {
+        Some(index as usize)
+    } else {
+        len.and_then(|len| len.checked_sub(index.unsigned_abs()))
+    }
+}
+
+/// Matches an array index against a Python style slice. Bounds are clamped to the array, `end`
+/// is exclusive and a negative step counts down from `start` towards `end`.
+fn match_step_to_index(
+    index: usize,
+    len: usize,
+    start: Option<isize>,
+    end: Option<isize>,
+    step: isize,
+) -> bool {
+    let (index, len) = (index as isize, len as isize);
+    let clamp = |b: isize, lo: isize, hi: isize| {
+        if b < 0 {
+            (b + len).max(lo)
+        } else {
+            b.min(hi)
         }
-        LexOperator::Pipe(_p) => {
-            todo!();
+    };
+    match step.cmp(&0) {
+        Ordering::Greater => {
+            let start = start.map_or(0, |s| clamp(s, 0, len));
+            let end = end.map_or(len, |e| clamp(e, 0, len));
+            start <= index && index < end && (index - start) % step == 0
         }
-        LexOperator::Generic(g) => {
-            return match_slice_to_key(key, &mut g.clone());
+        Ordering::Less => {
+            let start = start.map_or(len - 1, |s| clamp(s, -1, len - 1));
+            let end = end.map_or(-1, |e| clamp(e, -1, len - 1));
+            end < index && index <= start && (start - index) % step == 0
         }
+        Ordering::Equal => false,
     }
 }
 
-fn match_slice_to_key(key: &str, query: &mut GenericObjectIndex) -> bool {
-    let key_comp: ComType = key.into();
+fn match_slice_to_key(key: &str, query: &GenericObjectIndex, len: Option<usize>) -> bool {
+    let index = key.parse::<usize>().ok();
     match query {
         GenericObjectIndex::Wildcard => true,
+        // Filters are matched against the values, see `filter_matches`.
+        GenericObjectIndex::Filter(_) => false,
+        // Globs only select map keys, see `query_map_w_generic_object_index`.
+        GenericObjectIndex::Glob(_) => false,
         GenericObjectIndex::Slice(slice) => {
             for s in slice {
                 match s {
                     Slicer::Index(i) => {
-                        if key_comp == ComType::from(i) {
+                        if index == Some(*i) {
                             return true;
                         }
                     }
                     Slicer::Slice(f, t) => {
-                        if key_comp <= ComType::from(f) && key_comp >= ComType::from(t) {
+                        // A start before the beginning of the array is clamped to the first
+                        // element, so `[-5]` on a shorter array selects all of it.
+                        let from = match resolve_index(*f, len) {
+                            Some(from) => from,
+                            None if len.is_some() => 0,
+                            None => continue,
+                        };
+                        let to = match t.map(|t| resolve_index(t, len)) {
+                            None => None,
+                            Some(Some(to)) => Some(to),
+                            Some(None) => continue,
+                        };
+                        if index.is_some_and(|i| i >= from && to.is_none_or(|to| i <= to)) {
                             return true;
                         }
                     }
-                    Slicer::Ident(ident) => {
-                        if let Ok(ref i) = ident.parse::<usize>() {
-                            if key_comp == ComType::from(i) {
+                    Slicer::Step(start, end, step) => {
+                        // Step slices only apply to arrays, so map keys never match them.
+                        if let (Some(index), Some(len)) = (index, len) {
+                            if match_step_to_index(index, len, *start, *end, step.unwrap_or(1)) {
                                 return true;
                             }
                         }
                     }
+                    Slicer::KeyRange(from, to) => {
+                        if len.is_none()
+                            && from
+                                .as_deref()
+                                .is_none_or(|f| compare_keys(key, f) != Ordering::Less)
+                            && to
+                                .as_deref()
+                                .is_none_or(|t| compare_keys(key, t) != Ordering::Greater)
+                        {
+                            return true;
+                        }
+                    }
+                    Slicer::Ident(ident) => {
+                        // On maps an identifier selects the key of that exact name, while
+                        // arrays only have numeric keys to compare it with.
+                        if len.is_none() && key == ident {
+                            return true;
+                        }
+                        if index.is_some() && index == ident.parse::<usize>().ok() {
+                            return true;
+                        }
+                    }
                 }
             }
-            return false;
+            false
         }
     }
 }
 
-// fn array_items(v: Value, lex: LexicalOperations) -> {
-//
-//     let mut tmp_value = Vec::new();
-//     for (k, v) in v.into_iter().enumerate() {
-//         if key_match_array(&k, &key_query) {
-//             tmp_value =
-//                 vec![tmp_value, query_processor(v, query).unwrap_or_default()].concat();
-//         }
-//     }
-//     Ok(tmp_value)
-// }
-
 #[cfg(test)]
 pub mod test {
-    use crate::{query, ComType};
-    use data_query_lexical::{compile, LexOperator};
+    use crate::{compare_keys, query};
+    #[cfg(not(feature = "jq"))]
+    use crate::{
+        query_as, query_first, query_one, query_opt, query_refs, query_try_for_each, query_vec_as,
+        query_with_paths, MissingPath, Query, QueryError, QueryOptions,
+    };
+    #[cfg(not(feature = "jq"))]
+    use data_query_lexical::compile;
+    #[cfg(not(feature = "jq"))]
+    use data_query_lexical::GenericObjectIndex;
+    use data_query_lexical::LexOperator;
+    #[cfg(not(feature = "jq"))]
+    use serde_derive::Deserialize;
     use serde_derive::Serialize;
     use serde_json::Value;
+    use std::cmp::Ordering;
     use std::collections::{HashMap, LinkedList};
-    use std::iter::Map;
+    #[cfg(not(feature = "jq"))]
+    use std::ops::ControlFlow;
+    #[cfg(not(feature = "jq"))]
+    use std::sync::mpsc;
+    #[cfg(not(feature = "jq"))]
+    use std::thread;
 
     const TEST_OBJECT_RAW: &str = r##"{"apiVersion":"v1","kind":"Pod","metadata":{"annotations":{"kubectl.kubernetes.io/default-container":"wordpress","kubectl.kubernetes.io/default-logs-container":"wordpress","kubectl.kubernetes.io/restartedAt":"2022-06-07T20:38:55+09:00","prometheus.io/path":"/stats/prometheus","prometheus.io/port":"15020","prometheus.io/scrape":"true","sidecar.istio.io/status":"{\"initContainers\":[\"istio-init\"],\"containers\":[\"istio-proxy\"],\"volumes\":[\"istio-envoy\",\"istio-data\",\"istio-podinfo\",\"istio-token\",\"istiod-ca-cert\"],\"imagePullSecrets\":null,\"revision\":\"default\"}"},"creationTimestamp":"2022-06-07T11:38:55Z","generateName":"katsuoryuu-org-wordpress-b94d59c49-","labels":{"app.kubernetes.io/instance":"katsuoryuu-org","app.kubernetes.io/managed-by":"Helm","app.kubernetes.io/name":"wordpress","helm.sh/chart":"wordpress-13.1.1","pod-template-hash":"b94d59c49","security.istio.io/tlsMode":"istio","service.istio.io/canonical-name":"wordpress","service.istio.io/canonical-revision":"latest"},"managedFields":[{"apiVersion":"v1","fieldsType":"FieldsV1","fieldsV1":{"f:metadata":{"f:annotations":{".":{},"f:kubectl.kubernetes.io/restartedAt":{}},"f:generateName":{},"f:labels":{".":{},"f:app.kubernetes.io/instance":{},"f:app.kubernetes.io/managed-by":{},"f:app.kubernetes.io/name":{},"f:helm.sh/chart":{},"f:pod-template-hash":{}},"f:ownerReferences":{".":{},"k:{\"uid\":\"4a5f15a1-0380-4c48-9980-52beb6173eaa\"}":{}}},"f:spec":{"f:affinity":{".":{},"f:podAntiAffinity":{".":{},"f:preferredDuringSchedulingIgnoredDuringExecution":{}}},"f:containers":{"k:{\"name\":\"wordpress\"}":{".":{},"f:env":{".":{},"k:{\"name\":\"ALLOW_EMPTY_PASSWORD\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"APACHE_HTTPS_PORT_NUMBER\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"APACHE_HTTP_PORT_NUMBER\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"BITNAMI_DEBUG\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"MARIADB_HOST\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"MARIADB_PORT_NUMBER\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_AUTO_UPDATE_LEVEL\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_BLOG_NAME\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_DATABASE_NAME\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_DATABASE_PASSWORD\"}":{".":{},"f:name":{},"f:valueFrom":{".":{},"f:secretKeyRef":{}}},"k:{\"name\":\"WORDPRESS_DATABASE_USER\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_EMAIL\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_ENABLE_HTACCESS_PERSISTENCE\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_EXTRA_WP_CONFIG_CONTENT\"}":{".":{},"f:name":{}},"k:{\"name\":\"WORDPRESS_FIRST_NAME\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_HTACCESS_OVERRIDE_NONE\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_LAST_NAME\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_PASSWORD\"}":{".":{},"f:name":{},"f:valueFrom":{".":{},"f:secretKeyRef":{}}},"k:{\"name\":\"WORDPRESS_PLUGINS\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_SCHEME\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_SKIP_BOOTSTRAP\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_TABLE_PREFIX\"}":{".":{},"f:name":{},"f:value":{}},"k:{\"name\":\"WORDPRESS_USERNAME\"}":{".":{},"f:name":{},"f:value":{}}},"f:image":{},"f:imagePullPolicy":{},"f:livenessProbe":{".":{},"f:failureThreshold":{},"f:httpGet":{".":{},"f:path":{},"f:port":{},"f:scheme":{}},"f:initialDelaySeconds":{},"f:periodSeconds":{},"f:successThreshold":{},"f:timeoutSeconds":{}},"f:name":{},"f:ports":{".":{},"k:{\"containerPort\":8080,\"protocol\":\"TCP\"}":{".":{},"f:containerPort":{},"f:name":{},"f:protocol":{}},"k:{\"containerPort\":8443,\"protocol\":\"TCP\"}":{".":{},"f:containerPort":{},"f:name":{},"f:protocol":{}}},"f:readinessProbe":{".":{},"f:failureThreshold":{},"f:httpGet":{".":{},"f:path":{},"f:port":{},"f:scheme":{}},"f:initialDelaySeconds":{},"f:periodSeconds":{},"f:successThreshold":{},"f:timeoutSeconds":{}},"f:resources":{},"f:securityContext":{".":{},"f:runAsNonRoot":{},"f:runAsUser":{}},"f:terminationMessagePath":{},"f:terminationMessagePolicy":{},"f:volumeMounts":{".":{},"k:{\"mountPath\":\"/bitnami/wordpress\"}":{".":{},"f:mountPath":{},"f:name":{},"f:subPath":{}}}}},"f:dnsPolicy":{},"f:enableServiceLinks":{},"f:hostAliases":{".":{},"k:{\"ip\":\"127.0.0.1\"}":{".":{},"f:hostnames":{},"f:ip":{}}},"f:restartPolicy":{},"f:schedulerName":{},"f:securityContext":{".":{},"f:fsGroup":{}},"f:serviceAccount":{},"f:serviceAccountName":{},"f:terminationGracePeriodSeconds":{},"f:volumes":{".":{},"k:{\"name\":\"wordpress-data\"}":{".":{},"f:name":{},"f:persistentVolumeClaim":{".":{},"f:claimName":{}}}}}},"manager":"kube-controller-manager","operation":"Update","time":"2022-06-07T11:38:55Z"},{"apiVersion":"v1","fieldsType":"FieldsV1","fieldsV1":{"f:status":{"f:conditions":{"k:{\"type\":\"ContainersReady\"}":{".":{},"f:lastProbeTime":{},"f:lastTransitionTime":{},"f:status":{},"f:type":{}},"k:{\"type\":\"Initialized\"}":{".":{},"f:lastProbeTime":{},"f:lastTransitionTime":{},"f:status":{},"f:type":{}},"k:{\"type\":\"Ready\"}":{".":{},"f:lastProbeTime":{},"f:lastTransitionTime":{},"f:status":{},"f:type":{}}},"f:containerStatuses":{},"f:hostIP":{},"f:initContainerStatuses":{},"f:phase":{},"f:podIP":{},"f:podIPs":{".":{},"k:{\"ip\":\"172.17.0.27\"}":{".":{},"f:ip":{}}},"f:startTime":{}}},"manager":"kubelet","operation":"Update","subresource":"status","time":"2022-06-07T11:44:07Z"}],"name":"katsuoryuu-org-wordpress-b94d59c49-csvzr","namespace":"wordpress","ownerReferences":[{"apiVersion":"apps/v1","blockOwnerDeletion":true,"controller":true,"kind":"ReplicaSet","name":"katsuoryuu-org-wordpress-b94d59c49","uid":"4a5f15a1-0380-4c48-9980-52beb6173eaa"}],"resourceVersion":"77663255","uid":"8f03f916-4fd1-462f-a52c-0041b411179f"},"spec":{"affinity":{"podAntiAffinity":{"preferredDuringSchedulingIgnoredDuringExecution":[{"podAffinityTerm":{"labelSelector":{"matchLabels":{"app.kubernetes.io/instance":"katsuoryuu-org","app.kubernetes.io/name":"wordpress"}},"namespaces":["wordpress"],"topologyKey":"kubernetes.io/hostname"},"weight":1}]}},"containers":[{"env":[{"name":"BITNAMI_DEBUG","value":"false"},{"name":"ALLOW_EMPTY_PASSWORD","value":"yes"},{"name":"MARIADB_HOST","value":"mariadb.database-mysql.svc.cluster.local"},{"name":"MARIADB_PORT_NUMBER","value":"3306"},{"name":"WORDPRESS_DATABASE_NAME","value":"katsuoryuu.org"},{"name":"WORDPRESS_DATABASE_USER","value":"katsuoryuu.org"},{"name":"WORDPRESS_DATABASE_PASSWORD","valueFrom":{"secretKeyRef":{"key":"mariadb-password","name":"katsuoryuu-org-wordpress-externaldb"}}},{"name":"WORDPRESS_USERNAME","value":"user"},{"name":"WORDPRESS_PASSWORD","valueFrom":{"secretKeyRef":{"key":"wordpress-password","name":"katsuoryuu-org-wordpress"}}},{"name":"WORDPRESS_EMAIL","value":"user@example.com"},{"name":"WORDPRESS_FIRST_NAME","value":"FirstName"},{"name":"WORDPRESS_LAST_NAME","value":"LastName"},{"name":"WORDPRESS_HTACCESS_OVERRIDE_NONE","value":"no"},{"name":"WORDPRESS_ENABLE_HTACCESS_PERSISTENCE","value":"no"},{"name":"WORDPRESS_BLOG_NAME","value":"User's Blog!"},{"name":"WORDPRESS_SKIP_BOOTSTRAP","value":"no"},{"name":"WORDPRESS_TABLE_PREFIX","value":"wp_"},{"name":"WORDPRESS_SCHEME","value":"http"},{"name":"WORDPRESS_EXTRA_WP_CONFIG_CONTENT"},{"name":"WORDPRESS_AUTO_UPDATE_LEVEL","value":"none"},{"name":"WORDPRESS_PLUGINS","value":"none"},{"name":"APACHE_HTTP_PORT_NUMBER","value":"8080"},{"name":"APACHE_HTTPS_PORT_NUMBER","value":"8443"}],"image":"docker.io/bitnami/wordpress:5.9.2-debian-10-r4","imagePullPolicy":"IfNotPresent","livenessProbe":{"failureThreshold":6,"httpGet":{"path":"/app-health/wordpress/livez","port":15020,"scheme":"HTTP"},"initialDelaySeconds":120,"periodSeconds":10,"successThreshold":1,"timeoutSeconds":5},"name":"wordpress","ports":[{"containerPort":8080,"name":"http","protocol":"TCP"},{"containerPort":8443,"name":"https","protocol":"TCP"}],"readinessProbe":{"failureThreshold":6,"httpGet":{"path":"/app-health/wordpress/readyz","port":15020,"scheme":"HTTP"},"initialDelaySeconds":30,"periodSeconds":10,"successThreshold":1,"timeoutSeconds":5},"resources":{},"securityContext":{"runAsNonRoot":true,"runAsUser":1001},"terminationMessagePath":"/dev/termination-log","terminationMessagePolicy":"File","volumeMounts":[{"mountPath":"/bitnami/wordpress","name":"wordpress-data","subPath":"wordpress"},{"mountPath":"/var/run/secrets/kubernetes.io/serviceaccount","name":"kube-api-access-r74bw","readOnly":true}]},{"args":["proxy","sidecar","--domain","$(POD_NAMESPACE).svc.cluster.local","--proxyLogLevel=warning","--proxyComponentLogLevel=misc:error","--log_output_level=default:info","--concurrency","2"],"env":[{"name":"JWT_POLICY","value":"third-party-jwt"},{"name":"PILOT_CERT_PROVIDER","value":"istiod"},{"name":"CA_ADDR","value":"istiod.istio-system.svc:15012"},{"name":"POD_NAME","valueFrom":{"fieldRef":{"apiVersion":"v1","fieldPath":"metadata.name"}}},{"name":"POD_NAMESPACE","valueFrom":{"fieldRef":{"apiVersion":"v1","fieldPath":"metadata.namespace"}}},{"name":"INSTANCE_IP","valueFrom":{"fieldRef":{"apiVersion":"v1","fieldPath":"status.podIP"}}},{"name":"SERVICE_ACCOUNT","valueFrom":{"fieldRef":{"apiVersion":"v1","fieldPath":"spec.serviceAccountName"}}},{"name":"HOST_IP","valueFrom":{"fieldRef":{"apiVersion":"v1","fieldPath":"status.hostIP"}}},{"name":"PROXY_CONFIG","value":"{}\n"},{"name":"ISTIO_META_POD_PORTS","value":"[\n    {\"name\":\"http\",\"containerPort\":8080,\"protocol\":\"TCP\"}\n    ,{\"name\":\"https\",\"containerPort\":8443,\"protocol\":\"TCP\"}\n]"},{"name":"ISTIO_META_APP_CONTAINERS","value":"wordpress"},{"name":"ISTIO_META_CLUSTER_ID","value":"Kubernetes"},{"name":"ISTIO_META_INTERCEPTION_MODE","value":"REDIRECT"},{"name":"ISTIO_META_WORKLOAD_NAME","value":"katsuoryuu-org-wordpress"},{"name":"ISTIO_META_OWNER","value":"kubernetes://apis/apps/v1/namespaces/wordpress/deployments/katsuoryuu-org-wordpress"},{"name":"ISTIO_META_MESH_ID","value":"cluster.local"},{"name":"TRUST_DOMAIN","value":"cluster.local"},{"name":"ISTIO_KUBE_APP_PROBERS","value":"{\"/app-health/wordpress/livez\":{\"httpGet\":{\"path\":\"/wp-admin/install.php\",\"port\":8080,\"scheme\":\"HTTP\"},\"timeoutSeconds\":5},\"/app-health/wordpress/readyz\":{\"httpGet\":{\"path\":\"/wp-login.php\",\"port\":8080,\"scheme\":\"HTTP\"},\"timeoutSeconds\":5}}"}],"image":"docker.io/istio/proxyv2:1.13.3","imagePullPolicy":"IfNotPresent","name":"istio-proxy","ports":[{"containerPort":15090,"name":"http-envoy-prom","protocol":"TCP"}],"readinessProbe":{"failureThreshold":30,"httpGet":{"path":"/healthz/ready","port":15021,"scheme":"HTTP"},"initialDelaySeconds":1,"periodSeconds":2,"successThreshold":1,"timeoutSeconds":3},"resources":{"limits":{"cpu":"2","memory":"1Gi"},"requests":{"cpu":"100m","memory":"128Mi"}},"securityContext":{"allowPrivilegeEscalation":false,"capabilities":{"drop":["ALL"]},"privileged":false,"readOnlyRootFilesystem":true,"runAsGroup":1337,"runAsNonRoot":true,"runAsUser":1337},"terminationMessagePath":"/dev/termination-log","terminationMessagePolicy":"File","volumeMounts":[{"mountPath":"/var/run/secrets/istio","name":"istiod-ca-cert"},{"mountPath":"/var/lib/istio/data","name":"istio-data"},{"mountPath":"/etc/istio/proxy","name":"istio-envoy"},{"mountPath":"/var/run/secrets/tokens","name":"istio-token"},{"mountPath":"/etc/istio/pod","name":"istio-podinfo"},{"mountPath":"/var/run/secrets/kubernetes.io/serviceaccount","name":"kube-api-access-r74bw","readOnly":true}]}],"dnsPolicy":"ClusterFirst","enableServiceLinks":true,"hostAliases":[{"hostnames":["status.localhost"],"ip":"127.0.0.1"}],"initContainers":[{"args":["istio-iptables","-p","15001","-z","15006","-u","1337","-m","REDIRECT","-i","*","-x","","-b","*","-d","15090,15021,15020"],"image":"docker.io/istio/proxyv2:1.13.3","imagePullPolicy":"IfNotPresent","name":"istio-init","resources":{"limits":{"cpu":"2","memory":"1Gi"},"requests":{"cpu":"100m","memory":"128Mi"}},"securityContext":{"allowPrivilegeEscalation":false,"capabilities":{"add":["NET_ADMIN","NET_RAW"],"drop":["ALL"]},"privileged":false,"readOnlyRootFilesystem":false,"runAsGroup":0,"runAsNonRoot":false,"runAsUser":0},"terminationMessagePath":"/dev/termination-log","terminationMessagePolicy":"File","volumeMounts":[{"mountPath":"/var/run/secrets/kubernetes.io/serviceaccount","name":"kube-api-access-r74bw","readOnly":true}]}],"nodeName":"nebula","preemptionPolicy":"PreemptLowerPriority","priority":0,"restartPolicy":"Always","schedulerName":"default-scheduler","securityContext":{"fsGroup":1337},"serviceAccount":"default","serviceAccountName":"default","terminationGracePeriodSeconds":30,"tolerations":[{"effect":"NoExecute","key":"node.kubernetes.io/not-ready","operator":"Exists","tolerationSeconds":300},{"effect":"NoExecute","key":"node.kubernetes.io/unreachable","operator":"Exists","tolerationSeconds":300}],"volumes":[{"emptyDir":{"medium":"Memory"},"name":"istio-envoy"},{"emptyDir":{},"name":"istio-data"},{"downwardAPI":{"defaultMode":420,"items":[{"fieldRef":{"apiVersion":"v1","fieldPath":"metadata.labels"},"path":"labels"},{"fieldRef":{"apiVersion":"v1","fieldPath":"metadata.annotations"},"path":"annotations"}]},"name":"istio-podinfo"},{"name":"istio-token","projected":{"defaultMode":420,"sources":[{"serviceAccountToken":{"audience":"istio-ca","expirationSeconds":43200,"path":"istio-token"}}]}},{"configMap":{"defaultMode":420,"name":"istio-ca-root-cert"},"name":"istiod-ca-cert"},{"name":"wordpress-data","persistentVolumeClaim":{"claimName":"katsuoryuu-org-wordpress"}},{"name":"kube-api-access-r74bw","projected":{"defaultMode":420,"sources":[{"serviceAccountToken":{"expirationSeconds":3607,"path":"token"}},{"configMap":{"items":[{"key":"ca.crt","path":"ca.crt"}],"name":"kube-root-ca.crt"}},{"downwardAPI":{"items":[{"fieldRef":{"apiVersion":"v1","fieldPath":"metadata.namespace"},"path":"namespace"}]}}]}}]},"status":{"conditions":[{"lastTransitionTime":"2022-06-07T11:42:21Z","status":"True","type":"Initialized"},{"lastTransitionTime":"2022-06-07T11:44:07Z","status":"True","type":"Ready"},{"lastTransitionTime":"2022-06-07T11:44:07Z","status":"True","type":"ContainersReady"},{"lastTransitionTime":"2022-06-07T11:38:55Z","status":"True","type":"PodScheduled"}],"containerStatuses":[{"containerID":"docker://8a0e02954d6333f519f48acaad5967d127183d28959ea66ad64af0571e40bbab","image":"istio/proxyv2:1.13.3","imageID":"docker-pullable://istio/proxyv2@sha256:e8986efce46a7e1fcaf837134f453ea2b5e0750a464d0f2405502f8ddf0e2cd2","lastState":{},"name":"istio-proxy","ready":true,"restartCount":0,"started":true,"state":{"running":{"startedAt":"2022-06-07T11:43:31Z"}}},{"containerID":"docker://ee4d668df3e4a6eaedbc7194ae7ef410e97e2833aa113b21e93e4c664ba2bb20","image":"bitnami/wordpress:5.9.2-debian-10-r4","imageID":"docker-pullable://bitnami/wordpress@sha256:609a48d5d1fbda160ffe045f70e77e7221c10eded249cff150d00bdd7d8c41c3","lastState":{},"name":"wordpress","ready":true,"restartCount":0,"started":true,"state":{"running":{"startedAt":"2022-06-07T11:43:31Z"}}}],"hostIP":"192.168.80.224","initContainerStatuses":[{"containerID":"docker://08a9a89d7e7883ac964a984b9f8b2ecbbe81a82ac540bc698b9b838a88ce8a04","image":"istio/proxyv2:1.13.3","imageID":"docker-pullable://istio/proxyv2@sha256:e8986efce46a7e1fcaf837134f453ea2b5e0750a464d0f2405502f8ddf0e2cd2","lastState":{},"name":"istio-init","ready":true,"restartCount":0,"state":{"terminated":{"containerID":"docker://08a9a89d7e7883ac964a984b9f8b2ecbbe81a82ac540bc698b9b838a88ce8a04","exitCode":0,"finishedAt":"2022-06-07T11:42:21Z","reason":"Completed","startedAt":"2022-06-07T11:42:21Z"}}}],"phase":"Running","podIP":"172.17.0.27","podIPs":[{"ip":"172.17.0.27"}],"qosClass":"Burstable","startTime":"2022-06-07T11:38:55Z"}}"##;
 
@@ -367,13 +920,24 @@ pub mod test {
     }
 
     #[test]
-    fn test_com_type() {
-        let large = ComType::from(100);
-        let mid = ComType::from(50);
-        let low = ComType::from(10);
-        assert!(large == large);
-        assert!(mid < large);
-        assert!(low < mid);
+    fn test_compare_keys() {
+        assert_eq!(compare_keys("100", "100"), Ordering::Equal);
+        assert_eq!(compare_keys("50", "100"), Ordering::Less);
+        assert_eq!(compare_keys("10", "50"), Ordering::Less);
+
+        assert_eq!(compare_keys("a", "m"), Ordering::Less);
+        assert_eq!(compare_keys("a", "a"), Ordering::Equal);
+        assert_eq!(compare_keys("apple", "a"), Ordering::Greater);
+        assert_eq!(compare_keys("mango", "m"), Ordering::Greater);
+        // Numeric keys compare as numbers, and before every other key.
+        assert_eq!(compare_keys("9", "10"), Ordering::Less);
+        assert_eq!(compare_keys("15020", "a"), Ordering::Less);
+        assert_eq!(compare_keys("-1", "100"), Ordering::Less);
+        assert_eq!(compare_keys("-10", "-1"), Ordering::Less);
+        assert_eq!(compare_keys("1.5", "2"), Ordering::Less);
+        assert_eq!(compare_keys("1e3", "999"), Ordering::Greater);
+        assert_eq!(compare_keys("007", "7"), Ordering::Equal);
+        assert_eq!(compare_keys("nan", "inf"), Ordering::Greater);
     }
 
     #[test]
@@ -432,6 +996,1325 @@ pub mod test {
         println!("{:?}", query_res.unwrap());
     }
 
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_pipe() {
+        let lex = compile(".metadata | .namespace").unwrap();
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(data, lex).unwrap();
+        assert_eq!(query_res, serde_json::json!(["wordpress"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_pipe_precompile() {
+        let lex = precompile_lex!(.metadata | .namespace);
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(data, lex).unwrap();
+        assert_eq!(query_res, serde_json::json!(["wordpress"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_pipe_scalar() {
+        let lex = compile(".spec.containers[1] | .name | .").unwrap();
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(data, lex).unwrap();
+        assert_eq!(query_res, serde_json::json!(["istio-proxy"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_fan_out_evaluates_every_branch() {
+        let lex = compile(".friends[1,2].name").unwrap();
+        let query_res = query(User::default(), lex).unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["French Mcneil", "Carol Martin"])
+        );
+
+        let lex = compile(".friends[*].name").unwrap();
+        let query_res = query(User::default(), lex).unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["Colon Salazar", "French Mcneil", "Carol Martin"])
+        );
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_reuse_compiled() {
+        let lex = compile(".spec.containers[*] | .name").unwrap();
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let first = query(&data, &lex).unwrap();
+        let second = query(&data, &lex).unwrap();
+        assert_eq!(first, serde_json::json!(["wordpress", "istio-proxy"]));
+        assert_eq!(first, second);
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_ranges() {
+        let data = serde_json::json!({"items": [0, 1, 2, 3, 4, 5]});
+        let cases = [
+            (".items[0-2]", serde_json::json!([0, 1, 2])),
+            (".items[3-]", serde_json::json!([3, 4, 5])),
+            (".items[-2]", serde_json::json!([4, 5])),
+            (".items[-1]", serde_json::json!([5])),
+            (".items[-3--1]", serde_json::json!([3, 4, 5])),
+            (".items[1--5]", serde_json::json!([1])),
+            (".items[-10]", serde_json::json!([0, 1, 2, 3, 4, 5])),
+            (".items[-10--7]", serde_json::json!([])),
+            (".items[4-2]", serde_json::json!([])),
+        ];
+        for (lex, expected) in cases {
+            assert_eq!(query(&data, lex).unwrap(), expected, "{}", lex);
+        }
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_ranges_precompile() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, precompile_lex!(.status.conditions[-2].type)).unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["ContainersReady", "PodScheduled"])
+        );
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_step_slices() {
+        let data = serde_json::json!({"samples": [0, 1, 2, 3, 4, 5, 6]});
+        let cases = [
+            (".samples[::2]", serde_json::json!([0, 2, 4, 6])),
+            (".samples[1:5]", serde_json::json!([1, 2, 3, 4])),
+            (".samples[1:-1:3]", serde_json::json!([1, 4])),
+            (".samples[-3:]", serde_json::json!([4, 5, 6])),
+            (".samples[::-1]", serde_json::json!([6, 5, 4, 3, 2, 1, 0])),
+            (".samples[5:1:-2]", serde_json::json!([5, 3])),
+            (".samples[:-10:-3]", serde_json::json!([6, 3, 0])),
+            (".samples[10:20]", serde_json::json!([])),
+        ];
+        for (lex, expected) in cases {
+            assert_eq!(query(&data, lex).unwrap(), expected, "{}", lex);
+        }
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_step_slices_precompile() {
+        let data = serde_json::json!({"samples": [0, 1, 2, 3, 4, 5, 6]});
+        let query_res = query(&data, precompile_lex!(.samples[::-3])).unwrap();
+        assert_eq!(query_res, serde_json::json!([6, 3, 0]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_recursive_descent() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, ".spec..image").unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([
+                "docker.io/bitnami/wordpress:5.9.2-debian-10-r4",
+                "docker.io/istio/proxyv2:1.13.3",
+                "docker.io/istio/proxyv2:1.13.3"
+            ])
+        );
+
+        let query_res = query(&data, precompile_lex!(.spec..secretKeyRef.key)).unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["mariadb-password", "wordpress-password"])
+        );
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_recursive_descent_wildcard() {
+        let data = serde_json::json!({"a": [1, {"b": 2}], "c": 3});
+        let query_res = query(&data, "..[*]").unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([[1, {"b": 2}], 3, 1, {"b": 2}, 2])
+        );
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_filter() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, r#".spec.containers[?(.name == "wordpress")].image"#).unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["docker.io/bitnami/wordpress:5.9.2-debian-10-r4"])
+        );
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.spec.containers[?(.securityContext.runAsUser > 1001)].name),
+        )
+        .unwrap();
+        assert_eq!(query_res, serde_json::json!(["istio-proxy"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_filter_comparisons() {
+        let data = serde_json::json!({"friends": [
+            {"name": "Colon", "age": 25, "limit": 30},
+            {"name": "French", "age": 30.0, "limit": 20},
+            {"name": "Carol", "age": 35}
+        ]});
+        let cases = [
+            (
+                ".friends[?(.age == 30)].name",
+                serde_json::json!(["French"]),
+            ),
+            (
+                ".friends[?(.age != 30)].name",
+                serde_json::json!(["Colon", "Carol"]),
+            ),
+            (".friends[?(.age < 30)].name", serde_json::json!(["Colon"])),
+            (
+                ".friends[?(.age <= 30)].name",
+                serde_json::json!(["Colon", "French"]),
+            ),
+            (".friends[?(.age > 30)].name", serde_json::json!(["Carol"])),
+            (
+                ".friends[?(.age >= 30)].name",
+                serde_json::json!(["French", "Carol"]),
+            ),
+            (
+                ".friends[?(.age > .limit)].name",
+                serde_json::json!(["French", "Carol"]),
+            ),
+            (
+                ".friends[?(.limit == null)].name",
+                serde_json::json!(["Carol"]),
+            ),
+            (
+                r#".friends[?(.name < "D")].age"#,
+                serde_json::json!([25, 35]),
+            ),
+            (
+                ".friends[?(.name > 1)].age",
+                serde_json::json!([25, 30.0, 35]),
+            ),
+        ];
+        for (lex, expected) in cases {
+            assert_eq!(query(&data, lex).unwrap(), expected, "{}", lex);
+        }
+
+        // Values of different types compare in the order of `sort`: null < false < true <
+        // numbers < strings < arrays < objects.
+        let data = serde_json::json!([{"v": 1}, {"v": "b"}, {"v": null}, {"v": [0]}, {"v": {}}]);
+        let cases = [
+            (r#".[?(.v < "a")].v"#, serde_json::json!([1, null])),
+            (".[?(.v > 1)].v", serde_json::json!(["b", [0], {}])),
+            (".[?(.v >= [])].v", serde_json::json!([[0], {}])),
+            (
+                ".[?(null < false)].v",
+                serde_json::json!([1, "b", null, [0], {}]),
+            ),
+            (
+                ".[?(1 == 1.0)].v",
+                serde_json::json!([1, "b", null, [0], {}]),
+            ),
+            (".[?(false < null)].v", serde_json::json!([])),
+        ];
+        for (lex, expected) in cases {
+            assert_eq!(query(&data, lex).unwrap(), expected, "{}", lex);
+        }
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_filter_boolean_logic() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(
+            &data,
+            precompile_lex!(.spec.containers[?(.securityContext.runAsNonRoot == true and (.securityContext.runAsUser > 1001 or .securityContext.runAsUser == null))].name),
+        );
+        assert_eq!(query_res.unwrap(), serde_json::json!(["istio-proxy"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_filter_truthiness() {
+        let data = serde_json::json!({"items": [
+            {"id": 0, "flag": null},
+            {"id": 1, "flag": false},
+            {"id": 2, "flag": 0},
+            {"id": 3, "flag": ""},
+            {"id": 4, "flag": true},
+            {"id": 5}
+        ]});
+        let cases = [
+            (".items[?(.flag)].id", serde_json::json!([2, 3, 4])),
+            (".items[?(not .flag)].id", serde_json::json!([0, 1, 5])),
+            (".items[?(.flag and .id > 2)].id", serde_json::json!([3, 4])),
+            (
+                ".items[?(.flag or .id == 0)].id",
+                serde_json::json!([0, 2, 3, 4]),
+            ),
+            (
+                ".items[?(not (.flag or .id == 0))].id",
+                serde_json::json!([1, 5]),
+            ),
+        ];
+        for (lex, expected) in cases {
+            assert_eq!(query(&data, lex).unwrap(), expected, "{}", lex);
+        }
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_filter_map() {
+        let data = serde_json::json!({"ports": {"http": {"port": 80}, "https": {"port": 443}}});
+        let query_res = query(&data, ".ports[?(.port > 100)].port").unwrap();
+        assert_eq!(query_res, serde_json::json!([443]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_map_keys() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(
+            &data,
+            ".metadata.labels[app.kubernetes.io/name, pod-template-hash, missing]",
+        )
+        .unwrap();
+        assert_eq!(query_res, serde_json::json!(["wordpress", "b94d59c49"]));
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.metadata.managedFields[0].fieldsV1[f:metadata, f:spec].f:generateName?),
+        )
+        .unwrap();
+        assert_eq!(query_res, serde_json::json!([{}]));
+
+        // Mixed lists resolve against whichever container is found.
+        let data = serde_json::json!({
+            "list": ["a", "b"],
+            "map": {"0": "zero", "name": "named", "other": "other"}
+        });
+        let query_res = query(&data, ".list[0, name]").unwrap();
+        assert_eq!(query_res, serde_json::json!(["a"]));
+        let query_res = query(&data, ".map[0, name]").unwrap();
+        assert_eq!(query_res, serde_json::json!(["zero", "named"]));
+        let query_res = query(&data, "..[name]").unwrap();
+        assert_eq!(query_res, serde_json::json!(["named"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_cardinality() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        assert_eq!(
+            query_one(&data, ".metadata.namespace").unwrap(),
+            serde_json::json!("wordpress")
+        );
+        assert!(matches!(
+            query_one(&data, ".spec.containers[*].name"),
+            Err(QueryError::MultipleResults)
+        ));
+        assert!(matches!(
+            query_one(&data, ".spec.containers[?(.name == \"nginx\")]"),
+            Err(QueryError::NoResults)
+        ));
+
+        assert_eq!(
+            query_first(&data, precompile_lex!(..containerPort)).unwrap(),
+            serde_json::json!(8080)
+        );
+        assert!(matches!(
+            query_first(&data, ".spec.containers[?(.name == \"nginx\")]"),
+            Err(QueryError::NoResults)
+        ));
+
+        assert_eq!(
+            query_opt(&data, ".spec.nodeName").unwrap(),
+            Some(serde_json::json!("nebula"))
+        );
+        assert_eq!(query_opt(&data, ".spec.containers[5]?").unwrap(), None);
+        assert!(matches!(
+            query_opt(&data, ".spec.containers[*]"),
+            Err(QueryError::MultipleResults)
+        ));
+
+        // The first result is returned before the missing key of the second element is reached.
+        let data = serde_json::json!({"items": [{"a": 1}, {"b": 2}]});
+        assert!(matches!(
+            query(&data, ".items[*].a"),
+            Err(QueryError::KeyNotFound(_))
+        ));
+        assert_eq!(
+            query_first(&data, ".items[*].a").unwrap(),
+            serde_json::json!(1)
+        );
+        assert!(matches!(
+            query_one(&data, ".items[*].a"),
+            Err(QueryError::KeyNotFound(_))
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_with_paths() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let results =
+            query_with_paths(&data, ".spec.containers[1].env[?(.name == \"POD_NAME\")]").unwrap();
+        assert_eq!(results.len(), 1);
+        let (path, value) = &results[0];
+        assert_eq!(value["name"], serde_json::json!("POD_NAME"));
+        assert_eq!(path.to_json_pointer(), "/spec/containers/1/env/3");
+        assert_eq!(path.to_string(), ".spec.containers[1].env[3]");
+        assert_eq!(query_one(&data, &path.to_string()).unwrap(), *value);
+
+        let paths = |q: &str| {
+            query_with_paths(&data, q)
+                .unwrap()
+                .into_iter()
+                .map(|(path, _)| path.to_json_pointer())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths("..containerPort"),
+            vec![
+                "/spec/containers/0/ports/0/containerPort",
+                "/spec/containers/0/ports/1/containerPort",
+                "/spec/containers/1/ports/0/containerPort"
+            ]
+        );
+        assert_eq!(
+            paths(r#".metadata.annotations["prometheus.io/port"]"#),
+            vec!["/metadata/annotations/prometheus.io~1port"]
+        );
+        assert_eq!(
+            paths(".spec.containers[0] | .ports[-1].name, .image"),
+            vec![
+                "/spec/containers/0/ports/1/name",
+                "/spec/containers/0/image"
+            ]
+        );
+        assert_eq!(paths(".spec"), vec!["/spec"]);
+
+        // Quoted keys render back to a query selecting the same value.
+        let results = query_with_paths(&data, ".metadata.labels[app.kubernetes.io/*]").unwrap();
+        assert_eq!(results.len(), 3);
+        for (path, value) in results {
+            assert_eq!(query_one(&data, &path.to_string()).unwrap(), value);
+        }
+
+        assert!(matches!(
+            query_with_paths(&data, "{name: .metadata.name}"),
+            Err(QueryError::InvalidPathExpression(_))
+        ));
+        assert!(matches!(
+            query_with_paths(&data, ".metadata | [.name]"),
+            Err(QueryError::InvalidPathExpression(_))
+        ));
+
+        // A missing key never gets a path: it fails in strict mode and is skipped otherwise.
+        let data = serde_json::json!({"a": 1});
+        assert!(matches!(
+            query_with_paths(&data, ".a, .nope"),
+            Err(QueryError::KeyNotFound(k)) if k == "nope"
+        ));
+        let skip = Query::compile(".a, .nope")
+            .unwrap()
+            .with_options(QueryOptions::lenient());
+        let results = skip.evaluate_with_paths(&data).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.to_json_pointer(), "/a");
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_refs() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let containers = &data["spec"]["containers"];
+
+        let refs = query_refs(&data, ".spec.containers[*] | .name").unwrap();
+        assert_eq!(refs, vec!["wordpress", "istio-proxy"]);
+        assert!(std::ptr::eq(refs[0], &containers[0]["name"]));
+        assert!(std::ptr::eq(refs[1], &containers[1]["name"]));
+
+        let refs = query_refs(&data, r#"..ports[?(.name == "https")].containerPort"#).unwrap();
+        assert_eq!(refs, vec![8443]);
+        assert!(std::ptr::eq(
+            refs[0],
+            &containers[0]["ports"][1]["containerPort"]
+        ));
+
+        // Expressions made of paths still select from the document.
+        let refs = query_refs(&data, ".metadata | .name, .namespace").unwrap();
+        assert_eq!(
+            refs,
+            vec!["katsuoryuu-org-wordpress-b94d59c49-csvzr", "wordpress"]
+        );
+        assert!(std::ptr::eq(refs[1], &data["metadata"]["namespace"]));
+
+        let compiled = Query::compile(".spec.containers[*].resources.limits.cpu")
+            .unwrap()
+            .with_options(QueryOptions::lenient().with_missing(MissingPath::Null));
+        assert_eq!(
+            compiled.evaluate_refs(&data).unwrap(),
+            vec![&Value::Null, &serde_json::json!("2")]
+        );
+
+        assert!(matches!(
+            query_refs(&data, "[.spec.containers[*].name]"),
+            Err(QueryError::InvalidPathExpression(_))
+        ));
+        assert!(matches!(
+            query_refs(&data, ".metadata | {name}"),
+            Err(QueryError::InvalidPathExpression(_))
+        ));
+
+        // Borrowing returns what `query` returns under the same options.
+        let data = serde_json::json!({"a": 1});
+        assert!(matches!(
+            query(&data, ".a, .nope"),
+            Err(QueryError::KeyNotFound(k)) if k == "nope"
+        ));
+        assert!(matches!(
+            query_refs(&data, ".a, .nope"),
+            Err(QueryError::KeyNotFound(k)) if k == "nope"
+        ));
+        for options in [
+            QueryOptions::lenient(),
+            QueryOptions::lenient().with_missing(MissingPath::Null),
+        ] {
+            let compiled = Query::compile(".a, .nope").unwrap().with_options(options);
+            let refs = compiled.evaluate_refs(&data).unwrap();
+            let values = compiled.evaluate(&data).unwrap();
+            assert_eq!(refs.into_iter().cloned().collect::<Vec<_>>(), values);
+        }
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_as() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Port {
+            container_port: u16,
+            name: String,
+        }
+
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let namespace: String = query_as(&data, ".metadata.namespace").unwrap();
+        assert_eq!(namespace, "wordpress");
+        let port: Port = query_as(&data, precompile_lex!(.spec.containers[1].ports[0])).unwrap();
+        assert_eq!(
+            port,
+            Port {
+                container_port: 15090,
+                name: "http-envoy-prom".to_string()
+            }
+        );
+        assert!(matches!(
+            query_as::<String>(&data, ".spec.containers[*].name"),
+            Err(QueryError::MultipleResults)
+        ));
+
+        let ports: Vec<u16> = query_vec_as(&data, "..containerPort").unwrap();
+        assert_eq!(ports, vec![8080, 8443, 15090]);
+        let names: Vec<String> =
+            query_vec_as(&data, ".spec.containers[*] | {name} | .name").unwrap();
+        assert_eq!(names, vec!["wordpress", "istio-proxy"]);
+
+        // The error names the result that does not fit.
+        let error = query_vec_as::<u16>(
+            &data,
+            ".spec.containers[*].securityContext.runAsUser, .spec.nodeName",
+        )
+        .unwrap_err();
+        let QueryError::InvalidResultType(Some(path), _) = error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert_eq!(path.to_json_pointer(), "/spec/nodeName");
+        let error = query_as::<u16>(&data, ".spec.containers[0].resources").unwrap_err();
+        assert!(matches!(
+            error,
+            QueryError::InvalidResultType(Some(path), _) if path.to_string() == ".spec.containers[0].resources"
+        ));
+        let error = query_vec_as::<u16>(
+            &data,
+            ".spec.containers[1] | .ports[0].containerPort, .name",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            QueryError::InvalidResultType(Some(path), _) if path.to_json_pointer() == "/spec/containers/1/name"
+        ));
+        // Computed results have no path.
+        assert!(matches!(
+            query_vec_as::<u16>(&data, ".spec.containers[*] | [.name]"),
+            Err(QueryError::InvalidResultType(None, _))
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_try_for_each() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let mut ports = Vec::new();
+        let flow = query_try_for_each(&data, "..containerPort", |port| {
+            ports.push(port.clone());
+            ControlFlow::<()>::Continue(())
+        })
+        .unwrap();
+        assert_eq!(flow, ControlFlow::Continue(()));
+        assert_eq!(ports, vec![8080, 8443, 15090]);
+
+        // Computed values are handed over too, and breaking stops the walk inside them.
+        let mut names = Vec::new();
+        let flow = query_try_for_each(&data, ".spec.containers[*] | {name}", |object| {
+            names.push(object["name"].clone());
+            ControlFlow::Break(names.len())
+        })
+        .unwrap();
+        assert_eq!(flow, ControlFlow::Break(1));
+        assert_eq!(names, vec!["wordpress"]);
+
+        // The missing key of the second element is never reached.
+        let data = serde_json::json!({"items": [{"a": 1}, {"b": 2}]});
+        let flow = query_try_for_each(&data, ".items[*].a", |a| ControlFlow::Break(a.clone()));
+        assert_eq!(flow.unwrap(), ControlFlow::Break(serde_json::json!(1)));
+        assert!(matches!(
+            query_try_for_each(&data, ".items[*].a", |_| ControlFlow::<()>::Continue(())),
+            Err(QueryError::KeyNotFound(_))
+        ));
+
+        // Matches can be streamed through a bounded channel as they are found.
+        let data = serde_json::json!({"items": (0..100).collect::<Vec<_>>()});
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let consumer = thread::spawn(move || receiver.iter().take(3).collect::<Vec<Value>>());
+        let flow = query_try_for_each(&data, ".items[*]", |item| match sender.send(item.clone()) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })
+        .unwrap();
+        assert_eq!(flow, ControlFlow::Break(()));
+        assert_eq!(consumer.join().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_path_function() {
+        let data = serde_json::json!({"a": [{"b": 1}, {"b": 2}], "c": {"d": null}});
+        assert_eq!(
+            query(&data, ". | path(.a[*].b)").unwrap(),
+            serde_json::json!([["a", 0, "b"], ["a", 1, "b"]])
+        );
+        // A function call may start the query, as an object or a collection may.
+        assert_eq!(
+            query(&data, "path(..b)").unwrap(),
+            serde_json::json!([["a", 0, "b"], ["a", 1, "b"]])
+        );
+        // Missing keys follow the options, like everywhere outside of filters.
+        assert!(matches!(
+            query(&data, ".c | path(.d, .e)"),
+            Err(QueryError::KeyNotFound(k)) if k == "e"
+        ));
+        let lenient = Query::compile(".c | path(.d, .e)")
+            .unwrap()
+            .with_options(QueryOptions::lenient());
+        assert_eq!(lenient.execute(&data).unwrap(), serde_json::json!([["d"]]));
+        assert_eq!(
+            query(&data, ". | path(.a[1] | .b)").unwrap(),
+            serde_json::json!([["a", 1, "b"]])
+        );
+        assert!(matches!(
+            query(&data, r#". | path("a")"#),
+            Err(QueryError::InvalidPathExpression(_))
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_comma_and_collect() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, ".metadata.namespace, .spec.nodeName").unwrap();
+        assert_eq!(query_res, serde_json::json!(["wordpress", "nebula"]));
+
+        let compiled = Query::compile("[.spec.containers[*].name]").unwrap();
+        assert_eq!(
+            compiled.evaluate(&data).unwrap(),
+            vec![serde_json::json!(["wordpress", "istio-proxy"])]
+        );
+        let compiled = Query::compile(".spec.containers[*].name").unwrap();
+        assert_eq!(
+            compiled.evaluate(&data).unwrap(),
+            vec![
+                serde_json::json!("wordpress"),
+                serde_json::json!("istio-proxy")
+            ]
+        );
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.spec.containers[*] | {name, ports: [.ports[*].containerPort]}),
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([
+                {"name": "wordpress", "ports": [8080, 8443]},
+                {"name": "istio-proxy", "ports": [15090]}
+            ])
+        );
+
+        let data = serde_json::json!({"a": [1, 2], "b": 3});
+        let query_res = query(&data, ".a[*], .b | [., .]").unwrap();
+        assert_eq!(query_res, serde_json::json!([[1, 1], [2, 2], [3, 3]]));
+        let query_res = query(&data, "[.a[*], .b] | [.[1-]]").unwrap();
+        assert_eq!(query_res, serde_json::json!([[2, 3]]));
+        let query_res = query(&data, "[.missing?[*]]").unwrap();
+        assert_eq!(query_res, serde_json::json!([[]]));
+
+        // Paths inside an expression are as strict as the query.
+        let data = serde_json::json!({"s": "Hello World"});
+        assert!(matches!(
+            query(&data, ".nope, .s"),
+            Err(QueryError::KeyNotFound(k)) if k == "nope"
+        ));
+        assert!(matches!(
+            query(&data, "[.nope]"),
+            Err(QueryError::KeyNotFound(k)) if k == "nope"
+        ));
+        let query_res = query(&data, ".nope?, .s").unwrap();
+        assert_eq!(query_res, serde_json::json!(["Hello World"]));
+        let lenient = Query::compile("[.nope]")
+            .unwrap()
+            .with_options(QueryOptions::lenient());
+        assert_eq!(lenient.execute(&data).unwrap(), serde_json::json!([[]]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_object_construction() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(
+            &data,
+            r#"{name: .metadata.name, ns: .metadata.namespace, "node": .spec.nodeName}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([{
+                "name": "katsuoryuu-org-wordpress-b94d59c49-csvzr",
+                "ns": "wordpress",
+                "node": "nebula"
+            }])
+        );
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.spec.containers[*] | {name, port: .ports[*].containerPort}),
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([
+                {"name": "wordpress", "port": 8080},
+                {"name": "wordpress", "port": 8443},
+                {"name": "istio-proxy", "port": 15090}
+            ])
+        );
+
+        let data = serde_json::json!({"k": ["a", "b"], "v": [1, 2]});
+        let query_res = query(&data, "{(.k[*]): .v[*]} | {x: .}").unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([
+                {"x": {"a": 1}},
+                {"x": {"a": 2}},
+                {"x": {"b": 1}},
+                {"x": {"b": 2}}
+            ])
+        );
+        assert!(matches!(
+            query(&data, "{x: .nope}"),
+            Err(QueryError::KeyNotFound(k)) if k == "nope"
+        ));
+        let null = Query::compile("{missing}")
+            .unwrap()
+            .with_options(QueryOptions::lenient().with_missing(MissingPath::Null));
+        assert_eq!(
+            null.execute(&data).unwrap(),
+            serde_json::json!([{"missing": null}])
+        );
+        // A value without results builds no object.
+        let query_res = query(&data, "{x: .nope?}").unwrap();
+        assert_eq!(query_res, serde_json::json!([]));
+        assert!(matches!(
+            query(&data, "{(.v[0]): 1}"),
+            Err(QueryError::InvalidObjectKey(_))
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_key_ranges() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, precompile_lex!(.metadata.annotations[p..q])).unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["/stats/prometheus", "15020", "true"])
+        );
+
+        let data = serde_json::json!({
+            "80": "http", "443": "https", "8080": "alt", "15020": "metrics", "a": 1, "m": 2, "mango": 3
+        });
+        // Results follow the order of the map, where "443" comes before "80".
+        let query_res = query(&data, precompile_lex!(.[80..8080])).unwrap();
+        assert_eq!(query_res, serde_json::json!(["https", "http", "alt"]));
+        let query_res = query(&data, precompile_lex!(.[a..m])).unwrap();
+        assert_eq!(query_res, serde_json::json!([1, 2]));
+        let query_res = query(&data, precompile_lex!(.[1000..])).unwrap();
+        assert_eq!(query_res, serde_json::json!(["metrics", "alt", 1, 2, 3]));
+        let query_res = query(&data, precompile_lex!(.[..443])).unwrap();
+        assert_eq!(query_res, serde_json::json!(["https", "http"]));
+        // Numeric slices still only select numeric keys.
+        let query_res = query(&data, precompile_lex!(.[10000-])).unwrap();
+        assert_eq!(query_res, serde_json::json!(["metrics"]));
+        let query_res = query(serde_json::json!([1, 2]), precompile_lex!(.[0..1])).unwrap();
+        assert_eq!(query_res, serde_json::json!([]));
+
+        let data =
+            serde_json::json!({"-10": "a", "-1": "b", "1.5": "c", "1e3": "d", "5": "e", "x": "f"});
+        let query_res = query(&data, ".[-10..5]").unwrap();
+        assert_eq!(query_res, serde_json::json!(["b", "a", "c", "e"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_glob_keys() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, ".metadata.labels[app.kubernetes.io/*]").unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["katsuoryuu-org", "Helm", "wordpress"])
+        );
+
+        let query_res = query(&data, ".metadata.annotations[*/port]").unwrap();
+        assert_eq!(query_res, serde_json::json!(["15020"]));
+
+        let data = serde_json::json!({"v1": 1, "v2": 2, "vx": 3, "a*": 4, "list": ["v1"]});
+        let query_res = query(&data, ".[v[0-9]]").unwrap();
+        assert_eq!(query_res, serde_json::json!([1, 2]));
+        let query_res = query(&data, ".[v[!0-9]]").unwrap();
+        assert_eq!(query_res, serde_json::json!([3]));
+        let query_res = query(&data, r".[a\*]").unwrap();
+        assert_eq!(query_res, serde_json::json!([4]));
+        let query_res = query(&data, ".list[*1]").unwrap();
+        assert_eq!(query_res, serde_json::json!([]));
+
+        // The lexer rejects an unterminated class, but operators can be built by hand.
+        let unterminated = Query::from(LinkedList::from([LexOperator::Generic(
+            GenericObjectIndex::Glob("v[0-9".to_string()),
+        )]));
+        assert!(matches!(
+            unterminated.execute(&data),
+            Err(QueryError::InvalidGlobPattern(p)) if p == "v[0-9"
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_quoted_keys() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(&data, r#".metadata.annotations."prometheus.io/port""#).unwrap();
+        assert_eq!(query_res, serde_json::json!(["15020"]));
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.metadata.labels["helm.sh/chart", "app.kubernetes.io/name"]),
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["wordpress", "wordpress-13.1.1"])
+        );
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.metadata.annotations."kubectl.kubernetes.io/default-container"),
+        )
+        .unwrap();
+        assert_eq!(query_res, serde_json::json!(["wordpress"]));
+
+        let query_res = query(
+            &data,
+            r#".spec.affinity..[?(.podAffinityTerm.labelSelector.matchLabels."app.kubernetes.io/name" == "wordpress")].weight"#,
+        )
+        .unwrap();
+        assert_eq!(query_res, serde_json::json!([1]));
+
+        let data = serde_json::json!({"a\"b": {"0": "zero", "": "empty"}});
+        let query_res = query(&data, precompile_lex!(."a\"b"["0", ""])).unwrap();
+        assert_eq!(query_res, serde_json::json!(["empty", "zero"]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_optional_segment() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(
+            &data,
+            ".spec.containers[0].env[*].valueFrom?.secretKeyRef.name",
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([
+                "katsuoryuu-org-wordpress-externaldb",
+                "katsuoryuu-org-wordpress"
+            ])
+        );
+        assert!(matches!(
+            query(&data, ".spec.containers[0].env[*].valueFrom.secretKeyRef.name"),
+            Err(QueryError::KeyNotFound(key)) if key == "valueFrom"
+        ));
+
+        let query_res = query(&data, precompile_lex!(.spec.containers[*].args?[0])).unwrap();
+        assert_eq!(query_res, serde_json::json!(["proxy"]));
+        let query_res = query(&data, ".spec.containers.5?.name").unwrap();
+        assert_eq!(query_res, serde_json::json!([]));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_missing_path_options() {
+        use crate::{MissingPath, Query, QueryOptions};
+
+        let data = serde_json::json!({"items": [
+            {"id": 0, "meta": {"tag": "a"}},
+            {"id": 1, "meta": null},
+            {"id": 2}
+        ]});
+        let strict = Query::compile(".items[*].meta.tag").unwrap();
+        // The second item fails first, as its `meta` is null and has no keys.
+        assert!(matches!(
+            strict.execute(&data),
+            Err(QueryError::CannotIndex(t, key)) if t == "null" && key == ".tag"
+        ));
+        assert!(matches!(
+            query(&data, ".items[2].meta.tag"),
+            Err(QueryError::KeyNotFound(key)) if key == "meta"
+        ));
+        let lenient = strict.clone().with_options(QueryOptions::lenient());
+        assert_eq!(lenient.execute(&data).unwrap(), serde_json::json!(["a"]));
+        let null = strict
+            .clone()
+            .with_options(QueryOptions::lenient().with_missing(MissingPath::Null));
+        assert_eq!(
+            null.execute(&data).unwrap(),
+            serde_json::json!(["a", null, null])
+        );
+
+        let index = Query::compile(".items.7.id").unwrap();
+        assert!(matches!(
+            index.execute(&data),
+            Err(QueryError::IndexOutOfRange(7))
+        ));
+        let index = index.with_options(QueryOptions::lenient());
+        assert_eq!(index.execute(&data).unwrap(), serde_json::json!([]));
+        assert!(matches!(
+            query(&data, ".items.id"),
+            Err(QueryError::CannotUseIdentifierAsArrayKeyIndex(_))
+        ));
+
+        // A scalar cannot be indexed, which is an error only in strict mode.
+        let scalars = serde_json::json!({"s": "Hello World", "a": [0, 1]});
+        assert!(matches!(
+            query(&scalars, ".s.x"),
+            Err(QueryError::CannotIndex(t, key)) if t == "string" && key == ".x"
+        ));
+        assert!(matches!(
+            query(&scalars, ".a[1] | .x"),
+            Err(QueryError::CannotIndex(t, key)) if t == "number" && key == ".x"
+        ));
+        assert!(matches!(
+            query(&scalars, ".s[0]"),
+            Err(QueryError::CannotIndex(t, key)) if t == "string" && key == "[0]"
+        ));
+        assert_eq!(query(&scalars, ".s.x?").unwrap(), serde_json::json!([]));
+        for options in [
+            QueryOptions::lenient(),
+            QueryOptions::lenient().with_missing(MissingPath::Null),
+        ] {
+            for lex in [".s.x", ".a[1] | .x", ".s[0]"] {
+                let compiled = Query::compile(lex).unwrap().with_options(options);
+                assert_eq!(compiled.execute(&scalars).unwrap(), serde_json::json!([]));
+            }
+        }
+
+        // Paths inside filters read a missing key as `null` for that branch only.
+        let filter = Query::compile(r#"[?(.items[*].meta.tag == "a")] | .items[0].id"#)
+            .unwrap()
+            .with_options(QueryOptions::lenient());
+        assert_eq!(
+            filter.execute(serde_json::json!([data])).unwrap(),
+            serde_json::json!([0])
+        );
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_regex() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let query_res = query(
+            &data,
+            r#".spec.containers[0].env[?(.name =~ "^WORDPRESS_(FIRST|LAST)_")].value"#,
+        )
+        .unwrap();
+        assert_eq!(query_res, serde_json::json!(["FirstName", "LastName"]));
+
+        let query_res = query(
+            &data,
+            precompile_lex!(.spec.containers[0].env[?(.name | test("^mariadb_"; "i"))].name),
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!(["MARIADB_HOST", "MARIADB_PORT_NUMBER"])
+        );
+
+        let query_res = query(
+            &data,
+            r#".spec.containers[*].image | sub("^docker\\.io/"; "") | capture("(?P<repo>[^:]+):(?P<tag>.+)")"#,
+        )
+        .unwrap();
+        assert_eq!(
+            query_res,
+            serde_json::json!([
+                {"repo": "bitnami/wordpress", "tag": "5.9.2-debian-10-r4"},
+                {"repo": "istio/proxyv2", "tag": "1.13.3"}
+            ])
+        );
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_regex_functions() {
+        let data = serde_json::json!({"name": "façade-a1-b22", "items": [1, "x"]});
+        let cases = [
+            (r#".name | test("A\\d"; "i")"#, serde_json::json!([true])),
+            (r#".name | test("A\\d")"#, serde_json::json!([false])),
+            (
+                r#".name | match("(?P<letter>[a-z])(\\d+)"; "g") | .string"#,
+                serde_json::json!(["a1", "b22"]),
+            ),
+            (
+                r#".name | match("-(?P<letter>[a-z])(?P<digit>x)?")"#,
+                serde_json::json!([{
+                    "offset": 6,
+                    "length": 2,
+                    "string": "-a",
+                    "captures": [
+                        {"offset": 7, "length": 1, "string": "a", "name": "letter"},
+                        {"offset": -1, "length": 0, "string": null, "name": "digit"}
+                    ]
+                }]),
+            ),
+            (
+                r#".name | capture("(?P<letter>[a-z])(?P<digit>\\d+)"; "g") | .digit"#,
+                serde_json::json!(["1", "22"]),
+            ),
+            (r#".name | match("x*"; "gn")"#, serde_json::json!([])),
+            (
+                r##".name | sub("\\d"; "#")"##,
+                serde_json::json!(["façade-a#-b22"]),
+            ),
+            (
+                r##".name | gsub("\\d"; "#")"##,
+                serde_json::json!(["façade-a#-b##"]),
+            ),
+            (
+                r#".name | sub("(?P<l>[a-z])(?P<d>\\d)"; "$d$l"; "g")"#,
+                serde_json::json!(["façade-1a-2b2"]),
+            ),
+            (r#".items[?(. =~ "x")]"#, serde_json::json!(["x"])),
+        ];
+        for (q, expected) in cases {
+            assert_eq!(query(&data, q).unwrap(), expected, "{}", q);
+        }
+        assert!(matches!(
+            query(&data, r#".items[0] | test("x")"#),
+            Err(QueryError::InvalidFunctionInput(_, _))
+        ));
+        assert!(matches!(
+            query(&data, r#".name | test("(")"#),
+            Err(QueryError::RegexError(_))
+        ));
+        assert!(matches!(
+            query(&data, r#".name | test("x"; "q")"#),
+            Err(QueryError::InvalidRegexFlags(_))
+        ));
+        assert!(matches!(
+            query(&data, r#".name | test("x"; "i"; "g")"#),
+            Err(QueryError::UnknownFunction(f)) if f == "test/3"
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_aggregations() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let cases = [
+            (
+                ".status | sum(.containerStatuses[*].restartCount)",
+                serde_json::json!([0]),
+            ),
+            ("[.spec.containers[*].ports[*].containerPort] | sum", serde_json::json!([31613])),
+            ("[.spec.containers[*].ports[*].containerPort] | min, max", serde_json::json!([8080, 15090])),
+            (". | count(..containerPort)", serde_json::json!([3])),
+            (".spec.containers | count", serde_json::json!([2])),
+            (
+                ".spec.containers[*] | avg(.readinessProbe.periodSeconds, .readinessProbe.timeoutSeconds)",
+                serde_json::json!([7.5, 2.5]),
+            ),
+            (
+                ".spec.containers | max_by(.securityContext.runAsUser) | .name",
+                serde_json::json!(["istio-proxy"]),
+            ),
+            (
+                ".status.conditions | min_by(.lastTransitionTime) | .type",
+                serde_json::json!(["PodScheduled"]),
+            ),
+            // Ties keep the first element for `min_by` and the last one for `max_by`.
+            (
+                ".status.conditions | min_by(.status), max_by(.status) | .type",
+                serde_json::json!(["Initialized", "PodScheduled"]),
+            ),
+            (
+                ".spec.containers[0].resources | keys | sum, count, avg, min, max_by(.a)",
+                serde_json::json!([0, 0, null, null, null]),
+            ),
+        ];
+        for (q, expected) in cases {
+            assert_eq!(query(&data, q).unwrap(), expected, "{}", q);
+        }
+        assert!(matches!(
+            query(&data, ".spec | sum"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "sum"
+        ));
+        assert!(matches!(
+            query(&data, ".spec.containers | sum(.[*].name)"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "sum"
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_sorting() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let cases = [
+            (
+                ".spec.containers | sort_by(.name) | .[*].name",
+                serde_json::json!(["istio-proxy", "wordpress"]),
+            ),
+            (
+                ".status.conditions | sort_by(.lastTransitionTime) | .[*].type",
+                serde_json::json!(["PodScheduled", "Initialized", "Ready", "ContainersReady"]),
+            ),
+            (
+                "[.spec.containers[*].image, .spec.initContainers[*].image] | unique",
+                serde_json::json!([[
+                    "docker.io/bitnami/wordpress:5.9.2-debian-10-r4",
+                    "docker.io/istio/proxyv2:1.13.3"
+                ]]),
+            ),
+            // `unique_by` keeps the first element of every key.
+            (
+                ".status.conditions | unique_by(.lastTransitionTime) | .[*].type",
+                serde_json::json!(["PodScheduled", "Initialized", "Ready"]),
+            ),
+            (
+                "[.spec.containers[1].args[0-1], .spec.containers[*].ports[0].containerPort] | sort, reverse",
+                serde_json::json!([[8080, 15090, "proxy", "sidecar"], [15090, 8080, "sidecar", "proxy"]]),
+            ),
+            (".metadata.namespace | reverse", serde_json::json!(["sserpdrow"])),
+            // A container without `args` has no key, which sorts before any other.
+            (
+                ".spec.containers | min_by(.args) | .name",
+                serde_json::json!(["wordpress"]),
+            ),
+        ];
+        for (q, expected) in cases {
+            let compiled = Query::compile(q)
+                .unwrap()
+                .with_options(QueryOptions::lenient());
+            assert_eq!(compiled.execute(&data).unwrap(), expected, "{}", q);
+        }
+        assert!(matches!(
+            query(&data, ".metadata | sort"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "sort"
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_grouping() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let cases = [
+            (
+                ".spec.volumes | group_by(keys) | .[*] | length",
+                serde_json::json!([1, 1, 2, 1, 2]),
+            ),
+            (
+                ".spec.volumes | group_by(.emptyDir) | .[*] | .[*].name",
+                serde_json::json!([
+                    "istio-podinfo",
+                    "istio-token",
+                    "istiod-ca-cert",
+                    "wordpress-data",
+                    "kube-api-access-r74bw",
+                    "istio-data",
+                    "istio-envoy"
+                ]),
+            ),
+            (
+                "[.spec.containers[*].volumeMounts[*], .spec.initContainers[*].volumeMounts[*]] | count_by(.name)",
+                serde_json::json!([{
+                    "istio-data": 1,
+                    "istio-envoy": 1,
+                    "istio-podinfo": 1,
+                    "istio-token": 1,
+                    "istiod-ca-cert": 1,
+                    "kube-api-access-r74bw": 3,
+                    "wordpress-data": 1
+                }]),
+            ),
+            // Every port of a container counts under its protocol.
+            (
+                ".spec.containers | count_by(.ports[*].protocol)",
+                serde_json::json!([{"TCP": 3}]),
+            ),
+            (
+                "[.spec.containers[*].ports[*]] | to_object(.name; .containerPort)",
+                serde_json::json!([{"http": 8080, "https": 8443, "http-envoy-prom": 15090}]),
+            ),
+            (
+                ".spec.containers | to_object(.name) | keys",
+                serde_json::json!([["istio-proxy", "wordpress"]]),
+            ),
+            (
+                r#".spec.volumes | group_by(has("projected")) | to_object(.[0] | has("projected"); [.[*].name])"#,
+                serde_json::json!([{
+                    "false": ["istio-envoy", "istio-data", "istio-podinfo", "istiod-ca-cert", "wordpress-data"],
+                    "true": ["istio-token", "kube-api-access-r74bw"]
+                }]),
+            ),
+            (
+                ".status.conditions | group_by(.status) | to_object(.[0].status; count)",
+                serde_json::json!([{"True": 4}]),
+            ),
+        ];
+        for (q, expected) in cases {
+            let compiled = Query::compile(q)
+                .unwrap()
+                .with_options(QueryOptions::lenient());
+            assert_eq!(compiled.execute(&data).unwrap(), expected, "{}", q);
+        }
+        assert!(matches!(
+            query(&data, ".spec.containers | to_object(.ports)"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "to_object"
+        ));
+        assert!(matches!(
+            query(&data, ".metadata | group_by(.name)"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "group_by"
+        ));
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_query_introspection_functions() {
+        let data: Value = serde_json::from_str(TEST_OBJECT_RAW).unwrap();
+        let cases = [
+            (".spec.containers | length", serde_json::json!([2])),
+            (".metadata.name | length", serde_json::json!([40])),
+            (".spec.nodeSelector | length", serde_json::json!([0])),
+            (
+                ".spec.securityContext | keys",
+                serde_json::json!([["fsGroup"]]),
+            ),
+            (
+                ".spec.containers[1].resources.limits | keys, values",
+                serde_json::json!([["cpu", "memory"], ["2", "1Gi"]]),
+            ),
+            (".spec.containers | keys", serde_json::json!([[0, 1]])),
+            (
+                ".spec.containers[0].securityContext[*] | type",
+                serde_json::json!(["boolean", "number"]),
+            ),
+            (
+                r#".spec.containers[*] | has("args")"#,
+                serde_json::json!([false, true]),
+            ),
+            (
+                ".spec.containers | has(1), has(2)",
+                serde_json::json!([true, false]),
+            ),
+            (
+                r#".spec.containers[?(.readinessProbe | has("exec") or has("httpGet"))].name"#,
+                serde_json::json!(["wordpress", "istio-proxy"]),
+            ),
+            (
+                ".spec.containers[*].name | in({wordpress: true})",
+                serde_json::json!([true, false]),
+            ),
+            (
+                r#".spec.containers[*].ports[*] | {name, type: (.containerPort | type)}"#,
+                serde_json::json!([
+                    {"name": "http", "type": "number"},
+                    {"name": "https", "type": "number"},
+                    {"name": "http-envoy-prom", "type": "number"}
+                ]),
+            ),
+        ];
+        for (q, expected) in cases {
+            let compiled = Query::compile(q)
+                .unwrap()
+                .with_options(QueryOptions::lenient().with_missing(MissingPath::Null));
+            assert_eq!(compiled.execute(&data).unwrap(), expected, "{}", q);
+        }
+
+        let data = serde_json::json!({"n": -3.5, "i": -4, "b": true});
+        assert_eq!(
+            query(&data, ".n | length").unwrap(),
+            serde_json::json!([3.5])
+        );
+        assert_eq!(query(&data, ".i | length").unwrap(), serde_json::json!([4]));
+        assert!(matches!(
+            query(&data, ".b | length"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "length"
+        ));
+        assert!(matches!(
+            query(&data, ".n | keys"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "keys"
+        ));
+        assert!(matches!(
+            query(&data, ". | has(0)"),
+            Err(QueryError::InvalidFunctionInput(f, _)) if f == "has"
+        ));
+        assert!(matches!(
+            query(&data, ". | lenght"),
+            Err(QueryError::UnknownFunction(f)) if f == "lenght/0"
+        ));
+        assert!(matches!(
+            query(&data, ". | keys(.n)"),
+            Err(QueryError::UnknownFunction(f)) if f == "keys/1"
+        ));
+    }
+
     #[cfg(feature = "jq")]
     #[test]
     fn jq_test() {
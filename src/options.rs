@@ -0,0 +1,54 @@
+/// What a query does when a key or an array index on its path does not exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingPath {
+    /// Fail the whole query with `QueryError::KeyNotFound` or `QueryError::IndexOutOfRange`, or
+    /// with `QueryError::CannotIndex` when the value is neither an object nor an array.
+    #[default]
+    Error,
+    /// Drop the branch, so it contributes nothing to the results.
+    Skip,
+    /// Read the missing value as `null`, and keep reading `null` for any key below it. Keys and
+    /// indices of other scalars are skipped.
+    Null,
+}
+
+/// Options controlling how a [`Query`](crate::Query) is evaluated.
+///
+/// ```
+/// use data_query::{Query, QueryOptions};
+///
+/// let data = serde_json::json!({"env": [{"name": "A", "value": "1"}, {"name": "B"}]});
+/// let query = Query::compile(".env[*].value").unwrap();
+/// assert!(query.execute(&data).is_err());
+/// let query = query.with_options(QueryOptions::lenient());
+/// assert_eq!(query.execute(&data).unwrap(), serde_json::json!(["1"]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryOptions {
+    missing: MissingPath,
+}
+
+impl QueryOptions {
+    /// Missing keys and indices fail the query. This is the default.
+    pub fn strict() -> Self {
+        Self {
+            missing: MissingPath::Error,
+        }
+    }
+
+    /// Missing keys and indices are skipped.
+    pub fn lenient() -> Self {
+        Self {
+            missing: MissingPath::Skip,
+        }
+    }
+
+    pub fn with_missing(mut self, missing: MissingPath) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    pub fn missing(&self) -> MissingPath {
+        self.missing
+    }
+}
@@ -0,0 +1,165 @@
+use crate::expression::evaluate_expression;
+use crate::function::array_input;
+use crate::{Query, QueryError, QueryResult};
+use data_query_lexical::Expression;
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+/// Orders numbers by value regardless of their representation.
+pub(crate) fn compare_numbers(lhs: &Number, rhs: &Number) -> Option<Ordering> {
+    if let (Some(l), Some(r)) = (lhs.as_i64(), rhs.as_i64()) {
+        Some(l.cmp(&r))
+    } else if let (Some(l), Some(r)) = (lhs.as_u64(), rhs.as_u64()) {
+        Some(l.cmp(&r))
+    } else {
+        lhs.as_f64()?.partial_cmp(&rhs.as_f64()?)
+    }
+}
+
+/// The total ordering of values, as in jq: `null` < `false` < `true` < numbers < strings < arrays
+/// < objects. Strings compare by their characters and arrays element by element. Objects compare
+/// their sorted keys first, then their values in the order of the keys.
+pub(crate) fn total_cmp(lhs: &Value, rhs: &Value) -> Ordering {
+    match (lhs, rhs) {
+        (Value::Bool(l), Value::Bool(r)) => l.cmp(r),
+        // Numbers read from JSON are never NaN, so they always compare.
+        (Value::Number(l), Value::Number(r)) => compare_numbers(l, r).unwrap_or(Ordering::Equal),
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        (Value::Array(l), Value::Array(r)) => compare_sequences(l.iter(), r.iter()),
+        (Value::Object(l), Value::Object(r)) => {
+            let mut l_keys = l.keys().collect::<Vec<_>>();
+            let mut r_keys = r.keys().collect::<Vec<_>>();
+            l_keys.sort();
+            r_keys.sort();
+            l_keys.cmp(&r_keys).then_with(|| {
+                compare_sequences(l_keys.iter().map(|k| &l[*k]), r_keys.iter().map(|k| &r[*k]))
+            })
+        }
+        _ => rank(lhs).cmp(&rank(rhs)),
+    }
+}
+
+/// Compares values element by element, where a sequence sorts before any longer one it starts.
+fn compare_sequences<'a>(
+    lhs: impl Iterator<Item = &'a Value>,
+    rhs: impl Iterator<Item = &'a Value>,
+) -> Ordering {
+    let mut rhs = rhs;
+    for l in lhs {
+        match rhs.next() {
+            Some(r) => match total_cmp(l, r) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+            None => return Ordering::Greater,
+        }
+    }
+    if rhs.next().is_some() {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// The position of the type of a value in the total ordering.
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    }
+}
+
+/// The key of every element, which is the array of every value `key` produces for it.
+pub(crate) fn sort_keys(
+    compiled: &Query,
+    key: &Expression,
+    elements: &[Value],
+) -> QueryResult<Vec<Value>> {
+    elements
+        .iter()
+        .map(|e| evaluate_expression(compiled, key, e).map(Value::Array))
+        .collect()
+}
+
+/// `sort`, `sort_by(key)`, `unique` and `unique_by(key)` on the input array. Sorting is stable,
+/// and `unique` keeps the first of the elements with an equal key.
+pub(crate) fn sort(
+    compiled: &Query,
+    name: &str,
+    key: Option<&Expression>,
+    data: &Value,
+) -> QueryResult<Value> {
+    let elements = array_input(name, data)?;
+    let keys = match key {
+        Some(key) => sort_keys(compiled, key, elements)?,
+        None => Vec::new(),
+    };
+    let key_of = |i: usize| keys.get(i).unwrap_or(&elements[i]);
+    let mut order = (0..elements.len()).collect::<Vec<_>>();
+    order.sort_by(|l, r| total_cmp(key_of(*l), key_of(*r)));
+    if name.starts_with("unique") {
+        order.dedup_by(|r, l| total_cmp(key_of(*l), key_of(*r)) == Ordering::Equal);
+    }
+    Ok(Value::Array(
+        order.into_iter().map(|i| elements[i].clone()).collect(),
+    ))
+}
+
+/// `reverse`, the elements of an array or the characters of a string in reverse order.
+pub(crate) fn reverse(data: &Value) -> QueryResult<Value> {
+    match data {
+        Value::Null => Ok(Value::Array(Vec::new())),
+        Value::Array(a) => Ok(Value::Array(a.iter().rev().cloned().collect())),
+        Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+        _ => Err(QueryError::InvalidFunctionInput(
+            "reverse".to_string(),
+            data.clone(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ordering::total_cmp;
+    use serde_json::json;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn test_total_cmp() {
+        let ascending = [
+            json!(null),
+            json!(false),
+            json!(true),
+            json!(-1),
+            json!(0.5),
+            json!(1),
+            json!(u64::MAX),
+            json!(""),
+            json!("a"),
+            json!("b"),
+            json!([]),
+            json!([1]),
+            json!([1, 2]),
+            json!([2]),
+            json!({}),
+            json!({"a": 2}),
+            json!({"a": 2, "b": 1}),
+            json!({"b": 0}),
+        ];
+        for (i, l) in ascending.iter().enumerate() {
+            for (j, r) in ascending.iter().enumerate() {
+                assert_eq!(total_cmp(l, r), i.cmp(&j), "{} {}", l, r);
+            }
+        }
+        assert_eq!(total_cmp(&json!(1), &json!(1.0)), Ordering::Equal);
+        assert_eq!(
+            total_cmp(&json!({"a": 1, "b": 2}), &json!({"b": 2, "a": 1})),
+            Ordering::Equal
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use data_query_lexical::{GenericObjectIndex, LexOperator, LexicalOperations, Slicer};
+use serde_json::Value;
+use std::collections::LinkedList;
+use std::fmt::{Display, Formatter};
+
+/// A single step from a value to one of its children.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Where a result was found, as the keys and indices leading to it from the queried document.
+///
+/// It renders to a JSON Pointer with [`Path::to_json_pointer`] and to a query through
+/// `Display`, which compiles back to a query selecting the same value.
+///
+/// ```
+/// use data_query::Query;
+///
+/// let data = serde_json::json!({"spec": {"containers": [{"name": "a"}, {"name": "b/c"}]}});
+/// let query = Query::compile(".spec.containers[1].name").unwrap();
+/// let results = query.evaluate_with_paths(&data).unwrap();
+/// let (path, value) = &results[0];
+/// assert_eq!(value, &serde_json::json!("b/c"));
+/// assert_eq!(path.to_json_pointer(), "/spec/containers/1/name");
+/// assert_eq!(path.to_string(), ".spec.containers[1].name");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Path(Vec<PathSegment>);
+
+impl Path {
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    /// Renders the path as a JSON Pointer (RFC 6901), where the document itself is `""`.
+    pub fn to_json_pointer(&self) -> String {
+        let mut pointer = String::new();
+        for segment in &self.0 {
+            pointer.push('/');
+            match segment {
+                PathSegment::Key(k) => pointer.push_str(&k.replace('~', "~0").replace('/', "~1")),
+                PathSegment::Index(i) => pointer.push_str(&i.to_string()),
+            }
+        }
+        pointer
+    }
+
+    /// The lexical operations of a query selecting exactly this path.
+    pub fn to_lexical(&self) -> LexicalOperations {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Key(k) => LexOperator::Identifier(k.clone()),
+                PathSegment::Index(i) => {
+                    LexOperator::Generic(GenericObjectIndex::Slice(LinkedList::from([
+                        Slicer::Index(*i),
+                    ])))
+                }
+            })
+            .collect::<LinkedList<_>>()
+            .into()
+    }
+}
+
+impl From<Vec<PathSegment>> for Path {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Self(segments)
+    }
+}
+
+/// The jq representation of a path, an array of keys and indices such as `["spec", 0]`.
+impl From<Path> for Value {
+    fn from(path: Path) -> Self {
+        Value::Array(
+            path.0
+                .into_iter()
+                .map(|segment| match segment {
+                    PathSegment::Key(k) => Value::String(k),
+                    PathSegment::Index(i) => Value::from(i),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_lexical())
+    }
+}
@@ -0,0 +1,413 @@
+use crate::function::RegexCache;
+use crate::glob::GlobCache;
+use crate::{query_processor, Path, QueryError, QueryOptions, QueryResult, Results};
+use data_query_lexical::{compile, Expression, GenericObjectIndex, LexOperator, LexicalOperations};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::LinkedList;
+use std::fmt::{Display, Formatter};
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A compiled query that can be evaluated any number of times.
+///
+/// The lexical operations are shared behind an `Arc`, so cloning a `Query` is cheap and the same
+/// query can be handed to several threads. Evaluation only ever borrows the query. Glob patterns
+/// and regular expressions written as literals in the query are compiled once, together with the
+/// query.
+///
+/// ```
+/// use data_query::Query;
+///
+/// let query: Query = ".friends[1] | .name".parse().unwrap();
+/// let data = serde_json::json!({"friends": [{"name": "Colon"}, {"name": "French"}]});
+/// assert_eq!(query.execute(&data).unwrap(), serde_json::json!(["French"]));
+/// assert_eq!(query.to_string(), ".friends[1] | .name");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    lexes: Arc<LexicalOperations>,
+    regexes: Arc<RegexCache>,
+    globs: Arc<GlobCache>,
+    options: QueryOptions,
+}
+
+impl Query {
+    /// Compiles a query string.
+    pub fn compile(query: &str) -> QueryResult<Self> {
+        compile(query).map(Self::from).map_err(QueryError::from)
+    }
+
+    /// The lexical operations this query was compiled to.
+    pub fn lexical(&self) -> &LexicalOperations {
+        &self.lexes
+    }
+
+    /// Replaces the options the query is evaluated with.
+    pub fn with_options(mut self, options: QueryOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> QueryOptions {
+        self.options
+    }
+
+    pub(crate) fn regexes(&self) -> &RegexCache {
+        &self.regexes
+    }
+
+    pub(crate) fn globs(&self) -> &GlobCache {
+        &self.globs
+    }
+
+    /// Runs the query against any serializable data, returning every match in a `Value::Array`.
+    pub fn execute<S: Serialize>(&self, s: S) -> QueryResult<Value> {
+        let data = serde_json::to_value(s).map_err(QueryError::from)?;
+        self.evaluate(&data).map(Value::Array)
+    }
+
+    /// Runs the query against an already serialized value.
+    pub fn evaluate(&self, data: &Value) -> QueryResult<Vec<Value>> {
+        self.evaluate_until(data, None)
+    }
+
+    /// Runs the query against any serializable data, returning every match together with its
+    /// path in the data.
+    ///
+    /// Fails with `QueryError::InvalidPathExpression` when a result is computed rather than
+    /// found in the data, such as a literal or a constructed object.
+    pub fn execute_with_paths<S: Serialize>(&self, s: S) -> QueryResult<Vec<(Path, Value)>> {
+        let data = serde_json::to_value(s).map_err(QueryError::from)?;
+        self.evaluate_with_paths(&data)
+    }
+
+    /// Runs the query against an already serialized value, returning every match together with
+    /// its path.
+    pub fn evaluate_with_paths(&self, data: &Value) -> QueryResult<Vec<(Path, Value)>> {
+        let mut results = Results::new(None).with_paths();
+        query_processor(self, data, self.lexes.iter(), &mut results, 0)?;
+        Ok(results
+            .into_paths()
+            .into_iter()
+            .map(|(path, value)| (path, value.clone()))
+            .collect())
+    }
+
+    /// Runs the query against an already serialized value, returning references to the matches
+    /// rather than copies of them.
+    ///
+    /// Fails with `QueryError::InvalidPathExpression` when a result is computed rather than
+    /// found in the data, such as a literal or a constructed object.
+    pub fn evaluate_refs<'a>(&self, data: &'a Value) -> QueryResult<Vec<&'a Value>> {
+        let mut results = Results::new(None).borrowed();
+        query_processor(self, data, self.lexes.iter(), &mut results, 0)?;
+        Ok(results.into_refs())
+    }
+
+    /// Runs the query against an already serialized value, handing every match to `f` as soon as
+    /// it is found, in document order. Breaking out of `f` stops the walk, leaving the rest of the
+    /// document unvisited, and returns the `Break` value. An expression such as a pipe into a
+    /// function, a `,` or an object construction computes all of its results before the first
+    /// one is handed over, so breaking only skips what follows them.
+    ///
+    /// ```
+    /// use data_query::Query;
+    /// use std::ops::ControlFlow;
+    ///
+    /// let query = Query::compile("..name").unwrap();
+    /// let data = serde_json::json!({"items": [{"name": "a"}, {"name": "b"}, {"name": "c"}]});
+    /// let mut names = Vec::new();
+    /// let flow = query.try_for_each(&data, |name| {
+    ///     names.push(name.clone());
+    ///     if names.len() == 2 {
+    ///         ControlFlow::Break(())
+    ///     } else {
+    ///         ControlFlow::Continue(())
+    ///     }
+    /// });
+    /// assert_eq!(flow.unwrap(), ControlFlow::Break(()));
+    /// assert_eq!(names, ["a", "b"]);
+    /// ```
+    pub fn try_for_each<B>(
+        &self,
+        data: &Value,
+        mut f: impl FnMut(&Value) -> ControlFlow<B>,
+    ) -> QueryResult<ControlFlow<B>> {
+        let mut broken = None;
+        {
+            let mut sink = |value: &Value| {
+                f(value).map_break(|b| {
+                    broken = Some(b);
+                })
+            };
+            let mut results = Results::new(None).with_sink(&mut sink);
+            query_processor(self, data, self.lexes.iter(), &mut results, 0)?;
+        }
+        Ok(match broken {
+            Some(b) => ControlFlow::Break(b),
+            None => ControlFlow::Continue(()),
+        })
+    }
+
+    /// Runs the query, expecting exactly one result, and deserializes it into `T`.
+    ///
+    /// Fails like [`Query::execute_one`], and with `QueryError::InvalidResultType` when the
+    /// result does not deserialize.
+    pub fn execute_as<T: DeserializeOwned>(&self, s: impl Serialize) -> QueryResult<T> {
+        let data = serde_json::to_value(s).map_err(QueryError::from)?;
+        let mut results = self.evaluate_with_result_paths(&data, Some(2))?;
+        if results.len() > 1 {
+            return Err(QueryError::MultipleResults);
+        }
+        let (path, result) = results.pop().ok_or(QueryError::NoResults)?;
+        serde_json::from_value(result).map_err(|e| QueryError::InvalidResultType(path, e))
+    }
+
+    /// Runs the query and deserializes every result into `T`.
+    ///
+    /// Fails with `QueryError::InvalidResultType` on the first result that does not deserialize.
+    pub fn execute_vec_as<T: DeserializeOwned>(&self, s: impl Serialize) -> QueryResult<Vec<T>> {
+        let data = serde_json::to_value(s).map_err(QueryError::from)?;
+        self.evaluate_with_result_paths(&data, None)?
+            .into_iter()
+            .map(|(path, result)| {
+                serde_json::from_value(result).map_err(|e| QueryError::InvalidResultType(path, e))
+            })
+            .collect()
+    }
+
+    /// Runs the query like [`Query::evaluate_until`], also tracking the path of every result
+    /// found in the document so a result that fails to deserialize can report where it is.
+    fn evaluate_with_result_paths(
+        &self,
+        data: &Value,
+        limit: Option<usize>,
+    ) -> QueryResult<Vec<(Option<Path>, Value)>> {
+        let mut results = Results::new(limit).with_result_paths();
+        query_processor(self, data, self.lexes.iter(), &mut results, 0)?;
+        Ok(results.into_values_with_paths())
+    }
+
+    /// Runs the query until it has produced `limit` results, leaving the rest of the document
+    /// unvisited. Expressions are evaluated in full before their results are walked, so the limit
+    /// is only checked between them.
+    fn evaluate_until(&self, data: &Value, limit: Option<usize>) -> QueryResult<Vec<Value>> {
+        let mut results = Results::new(limit);
+        query_processor(self, data, self.lexes.iter(), &mut results, 0)?;
+        Ok(results.into_values())
+    }
+
+    /// Runs the query, expecting exactly one result.
+    ///
+    /// Fails with `QueryError::NoResults` when nothing matches and with
+    /// `QueryError::MultipleResults` as soon as a second result is found, see
+    /// [`Query::execute_first`] for when that is.
+    pub fn execute_one<S: Serialize>(&self, s: S) -> QueryResult<Value> {
+        self.execute_opt(s)?.ok_or(QueryError::NoResults)
+    }
+
+    /// Runs the query until its first result, which is returned without walking the rest of
+    /// the document. Errors the query would only have hit afterwards are not reported.
+    ///
+    /// An expression such as a pipe into a function, a `,` or an object construction still
+    /// computes all of its results before the first one is returned, so only the walk past the
+    /// expression stops early.
+    ///
+    /// Fails with `QueryError::NoResults` when nothing matches.
+    pub fn execute_first<S: Serialize>(&self, s: S) -> QueryResult<Value> {
+        let data = serde_json::to_value(s).map_err(QueryError::from)?;
+        self.evaluate_until(&data, Some(1))?
+            .pop()
+            .ok_or(QueryError::NoResults)
+    }
+
+    /// Runs the query, expecting at most one result.
+    ///
+    /// Fails with `QueryError::MultipleResults` as soon as a second result is found, see
+    /// [`Query::execute_first`] for when that is.
+    pub fn execute_opt<S: Serialize>(&self, s: S) -> QueryResult<Option<Value>> {
+        let data = serde_json::to_value(s).map_err(QueryError::from)?;
+        let mut results = self.evaluate_until(&data, Some(2))?;
+        if results.len() > 1 {
+            return Err(QueryError::MultipleResults);
+        }
+        Ok(results.pop())
+    }
+}
+
+impl From<LexicalOperations> for Query {
+    fn from(lexes: LexicalOperations) -> Self {
+        Self {
+            regexes: Arc::new(RegexCache::new(&lexes)),
+            globs: Arc::new(GlobCache::new(&lexes)),
+            lexes: Arc::new(lexes),
+            options: QueryOptions::default(),
+        }
+    }
+}
+
+impl From<&LexicalOperations> for Query {
+    fn from(lexes: &LexicalOperations) -> Self {
+        Self::from(lexes.clone())
+    }
+}
+
+impl From<LinkedList<LexOperator>> for Query {
+    fn from(lexes: LinkedList<LexOperator>) -> Self {
+        Self::from(LexicalOperations::from(lexes))
+    }
+}
+
+impl From<&Query> for Query {
+    fn from(query: &Query) -> Self {
+        query.clone()
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::compile(s)
+    }
+}
+
+impl TryFrom<&str> for Query {
+    type Error = QueryError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::compile(s)
+    }
+}
+
+impl TryFrom<&String> for Query {
+    type Error = QueryError;
+
+    fn try_from(s: &String) -> Result<Self, Self::Error> {
+        Self::compile(s)
+    }
+}
+
+/// Two queries are equal when they compile to the same lexical operations and use the same
+/// options.
+impl PartialEq for Query {
+    fn eq(&self, other: &Self) -> bool {
+        self.lexes == other.lexes && self.options == other.options
+    }
+}
+
+impl Eq for Query {}
+
+/// Prints the canonical query string, which compiles back to the same query.
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.lexes)
+    }
+}
+
+/// Reads the operators and expressions of a query, see [`walk_operations`].
+pub(crate) trait Visitor {
+    fn operator(&mut self, _operator: &LexOperator) {}
+
+    fn expression(&mut self, _expression: &Expression) {}
+}
+
+/// Hands every operator and expression of `operations` to `visitor`, including those nested in
+/// pipes, filters, expressions and function arguments.
+pub(crate) fn walk_operations(operations: &LinkedList<LexOperator>, visitor: &mut impl Visitor) {
+    operations.iter().for_each(|o| walk_operator(o, visitor));
+}
+
+fn walk_operator(operator: &LexOperator, visitor: &mut impl Visitor) {
+    visitor.operator(operator);
+    match operator {
+        LexOperator::Pipe(p) => walk_operations(p, visitor),
+        LexOperator::Generic(GenericObjectIndex::Filter(e)) | LexOperator::Expression(e) => {
+            walk_expression(e, visitor)
+        }
+        LexOperator::RecursiveDescent(o) | LexOperator::Optional(o) => walk_operator(o, visitor),
+        LexOperator::Identifier(_) | LexOperator::Generic(_) => {}
+    }
+}
+
+fn walk_expression(expression: &Expression, visitor: &mut impl Visitor) {
+    visitor.expression(expression);
+    match expression {
+        Expression::Path(p) => walk_operations(p, visitor),
+        Expression::Literal(_) => {}
+        Expression::Compare(l, _, r)
+        | Expression::And(l, r)
+        | Expression::Or(l, r)
+        | Expression::Pipe(l, r)
+        | Expression::Comma(l, r) => {
+            walk_expression(l, visitor);
+            walk_expression(r, visitor);
+        }
+        Expression::Not(e) | Expression::Collect(e) => walk_expression(e, visitor),
+        Expression::Object(entries) => entries.iter().for_each(|(k, v)| {
+            walk_expression(k, visitor);
+            walk_expression(v, visitor);
+        }),
+        Expression::Function(_, arguments) => {
+            arguments.iter().for_each(|a| walk_expression(a, visitor))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Query;
+    use serde_json::json;
+    use std::borrow::Cow;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_query_is_send_sync() {
+        assert_send_sync::<Query>();
+    }
+
+    #[test]
+    fn test_query_shared_between_threads() {
+        let query = Arc::new(Query::compile(".items[*].id").unwrap());
+        let handles = (0..4)
+            .map(|i| {
+                let query = Arc::clone(&query);
+                thread::spawn(move || {
+                    let data = json!({"items": [{"id": i}, {"id": i + 1}]});
+                    query.evaluate(&data).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), vec![json!(i), json!(i + 1)]);
+        }
+    }
+
+    #[test]
+    fn test_query_caches_literal_regexes() {
+        let query =
+            Query::compile(r#".env[?(.name =~ "^A")] | .value | sub("b"; "c"; "g")"#).unwrap();
+        assert!(matches!(
+            query.regexes().get("^A", ""),
+            Ok(Cow::Borrowed(_))
+        ));
+        assert!(matches!(
+            query.regexes().get("b", "g"),
+            Ok(Cow::Borrowed(_))
+        ));
+        assert!(matches!(query.regexes().get("b", ""), Ok(Cow::Owned(_))));
+    }
+
+    #[test]
+    fn test_query_display() {
+        let query = Query::from(precompile_lex!(.metadata[1,2,4-6,hello] | .name));
+        assert_eq!(query.to_string(), ".metadata[1,2,4-6,hello] | .name");
+        assert_eq!(Query::compile(&query.to_string()).unwrap(), query);
+    }
+}